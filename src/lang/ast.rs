@@ -9,6 +9,8 @@ use crate::util::glob::Glob;
 use regex::Regex;
 use std::path::PathBuf;
 use crate::lang::scope::Scope;
+use num_bigint::BigInt;
+use bigdecimal::BigDecimal;
 
 pub struct JobListNode {
     pub jobs: Vec<JobNode>,
@@ -67,13 +69,16 @@ pub enum Node {
     Field(String),
     String(String),
     File(PathBuf),
-    Integer(i128),
+    Integer(BigInt),
     Float(f64),
+    Decimal(BigDecimal),
     GetItem(Box<Node>, Box<Node>),
     GetAttr(Box<Node>, String),
     Path(Box<Node>, String),
     Substitution(JobNode),
     Closure(Option<Vec<ParameterNode>>, JobListNode),
+    List(Vec<Node>),
+    Dict(Vec<(Node, Node)>),
 }
 
 fn propose_name(name: &str, v: ValueDefinition) -> ValueDefinition {
@@ -137,8 +142,9 @@ impl Node {
                 Node::Label(l) => ValueDefinition::Label(l.clone()),
                 Node::Regex(l) => ValueDefinition::Value(Value::Regex(l.clone(), to_crush_error(Regex::new(l.clone().as_ref()))?)),
                 Node::String(t) => ValueDefinition::Value(Value::string(unescape(t).as_str())),
-                Node::Integer(i) => ValueDefinition::Value(Value::Integer(*i)),
+                Node::Integer(i) => ValueDefinition::Value(Value::big_integer(i.clone())),
                 Node::Float(f) => ValueDefinition::Value(Value::Float(*f)),
+                Node::Decimal(d) => ValueDefinition::Value(Value::Decimal(d.clone())),
                 Node::GetAttr(node, label) => {
                     let parent = node.generate_argument(env)?;
                     match parent.unnamed_value()? {
@@ -164,6 +170,16 @@ impl Node {
                     };
                     ValueDefinition::ClosureDefinition(None, p, c.generate(env)?)
                 }
+                Node::List(l) => ValueDefinition::List(
+                    l.iter()
+                        .map(|e| e.generate_argument(env)?.unnamed_value())
+                        .collect::<CrushResult<Vec<_>>>()?),
+                Node::Dict(d) => ValueDefinition::Dict(
+                    d.iter()
+                        .map(|(k, v)| Ok((
+                            k.generate_argument(env)?.unnamed_value()?,
+                            v.generate_argument(env)?.unnamed_value()?)))
+                        .collect::<CrushResult<Vec<_>>>()?),
                 Node::Glob(g) => ValueDefinition::Value(Value::Glob(Glob::new(&g))),
                 Node::File(f) => ValueDefinition::Value(Value::File(f.clone())),
             }))
@@ -290,8 +306,8 @@ impl Node {
                 },
 
             Node::Cast(_, _) | Node::Glob(_) | Node::Label(_) | Node::Regex(_) | Node::Field(_) | Node::String(_) |
-            Node::Integer(_) | Node::Float(_) | Node::GetAttr(_, _) | Node::Path(_, _) | Node::Substitution(_) |
-            Node::Closure(_, _) | Node::File(_) => Ok(None),
+            Node::Integer(_) | Node::Float(_) | Node::Decimal(_) | Node::GetAttr(_, _) | Node::Path(_, _) | Node::Substitution(_) |
+            Node::Closure(_, _) | Node::File(_) | Node::List(_) | Node::Dict(_) => Ok(None),
         }
     }
 
@@ -313,6 +329,8 @@ impl Node {
     pub fn parse_label(s: &str) -> Box<Node> {
         if s.contains('%') || s.contains('?') {
             Box::from(Node::Glob(s.to_string()))
+        } else if s.starts_with('~') {
+            Box::from(Node::File(PathBuf::from(s)))
         } else if s.contains('/') {
             if s.starts_with('/') {
                 Box::from(Node::File(PathBuf::from(s)))
@@ -1,7 +1,7 @@
 use crate::lang::errors::{CrushResult, to_crush_error};
 use std::cmp::{min};
 use std::collections::{VecDeque};
-use std::io::{Error, Read, Write};
+use std::io::{Error, ErrorKind, Read, Write};
 use crossbeam::{Receiver, bounded, Sender};
 use std::fmt::{Debug, Formatter};
 use std::fs::File;
@@ -50,9 +50,10 @@ impl std::io::Read for ChannelReader {
                     self.buff = None;
                     Ok(res)
                 } else {
-                    dst.write_all(src)?;
-                    self.buff = Some(Box::from(&src[dst.len()..]));
-                    Ok(dst.len())
+                    let res = dst.len();
+                    dst.write_all(&src[..res])?;
+                    self.buff = Some(Box::from(&src[res..]));
+                    Ok(res)
                 }
             }
         }
@@ -66,8 +67,10 @@ struct ChannelWriter {
 impl std::io::Write for ChannelWriter {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
         let boxed_slice: Box<[u8]> = buf.into();
-        let _ = self.sender.send(boxed_slice);
-        Ok(buf.len())
+        match self.sender.send(boxed_slice) {
+            Ok(_) => Ok(buf.len()),
+            Err(_) => Err(Error::new(ErrorKind::BrokenPipe, "Reader has been closed")),
+        }
     }
 
     fn flush(&mut self) -> Result<(), Error> {
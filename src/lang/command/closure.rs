@@ -88,6 +88,10 @@ impl CrushCommand for Closure {
             this,
         })
     }
+
+    fn jobs(&self) -> Option<&[Job]> {
+        Some(&self.job_definitions)
+    }
 }
 
 struct ClosureSerializer<'a> {
@@ -248,6 +252,25 @@ impl<'a> ClosureSerializer<'a> {
                                 element: element.to_string(),
                             })
                         ),
+                    ValueDefinition::List(elements) =>
+                        model::value_definition::ValueDefinition::List(
+                            model::ListDefinition {
+                                elements: elements.iter()
+                                    .map(|e| self.value_definition(e))
+                                    .collect::<CrushResult<Vec<_>>>()?,
+                            }
+                        ),
+                    ValueDefinition::Dict(entries) =>
+                        model::value_definition::ValueDefinition::Dict(
+                            model::DictDefinition {
+                                entries: entries.iter()
+                                    .map(|(k, v)| Ok(model::DictEntryDefinition {
+                                        key: Some(self.value_definition(k)?),
+                                        value: Some(self.value_definition(v)?),
+                                    }))
+                                    .collect::<CrushResult<Vec<_>>>()?,
+                            }
+                        ),
                 }
             )
         })
@@ -401,6 +424,18 @@ impl<'a> ClosureDeserializer<'a> {
                 ValueDefinition::Path(
                     Box::from(self.value_definition(mandate(a.parent.as_ref(), "Invalid value definition")?)?),
                     a.element.clone()),
+            model::value_definition::ValueDefinition::List(l) =>
+                ValueDefinition::List(
+                    l.elements.iter()
+                        .map(|e| self.value_definition(e))
+                        .collect::<CrushResult<Vec<_>>>()?),
+            model::value_definition::ValueDefinition::Dict(d) =>
+                ValueDefinition::Dict(
+                    d.entries.iter()
+                        .map(|e| Ok((
+                            self.value_definition(mandate(e.key.as_ref(), "Invalid value definition")?)?,
+                            self.value_definition(mandate(e.value.as_ref(), "Invalid value definition")?)?)))
+                        .collect::<CrushResult<Vec<_>>>()?),
         })
     }
 }
@@ -22,6 +22,11 @@ pub trait CrushCommand: Help {
     fn help(&self) -> &dyn Help;
     fn serialize(&self, elements: &mut Vec<Element>, state: &mut SerializationState) -> CrushResult<usize>;
     fn bind(&self, this: Value) -> Box<dyn CrushCommand + Send + Sync>;
+    /**
+      The jobs a closure consists of, so that commands like group can recognize simple closures
+      such as {sum ^size}. Other commands have none.
+    */
+    fn jobs(&self) -> Option<&[Job]>;
 }
 
 pub trait TypeMap {
@@ -180,6 +185,10 @@ impl CrushCommand for SimpleCommand {
             this,
         })
     }
+
+    fn jobs(&self) -> Option<&[Job]> {
+        None
+    }
 }
 
 impl Help for SimpleCommand {
@@ -252,6 +261,10 @@ impl CrushCommand for ConditionCommand {
             this,
         })
     }
+
+    fn jobs(&self) -> Option<&[Job]> {
+        None
+    }
 }
 
 impl Help for ConditionCommand {
@@ -355,6 +368,10 @@ impl CrushCommand for BoundCommand {
             }
         )
     }
+
+    fn jobs(&self) -> Option<&[Job]> {
+        None
+    }
 }
 
 impl Help for BoundCommand {
@@ -3,7 +3,8 @@ use crate::lang::{argument::ArgumentDefinition, argument::ArgumentVecCompiler, v
 use crate::lang::scope::Scope;
 use crate::lang::errors::{error, CrushResult, Kind};
 use crate::util::thread::{handle, build};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::os::unix::fs::PermissionsExt;
 use crate::lang::execution_context::{JobContext, CompileContext};
 use std::ops::Deref;
 
@@ -13,14 +14,14 @@ pub struct CommandInvocation {
     arguments: Vec<ArgumentDefinition>,
 }
 
-fn resolve_external_command(name: &str, env: &Scope) -> CrushResult<Option<PathBuf>> {
+pub fn resolve_external_command(name: &str, env: &Scope) -> CrushResult<Option<PathBuf>> {
     if let Some(Value::List(path)) = env.get("cmd_path")? {
         let path_vec = path.dump();
         for val in path_vec {
             match val {
                 Value::File(el) => {
                     let full = el.join(name);
-                    if full.exists() {
+                    if is_executable(&full) {
                         return Ok(Some(full));
                     }
                 }
@@ -31,6 +32,16 @@ fn resolve_external_command(name: &str, env: &Scope) -> CrushResult<Option<PathB
     Ok(None)
 }
 
+/**
+  True if the path points to a regular file that has at least one executable bit set.
+*/
+fn is_executable(path: &Path) -> bool {
+    match path.metadata() {
+        Ok(meta) => meta.is_file() && meta.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
 fn arg_can_block(local_arguments: &Vec<ArgumentDefinition>, context: &mut CompileContext) -> bool {
     for arg in local_arguments {
         if arg.value.can_block(local_arguments, context) {
@@ -154,7 +165,17 @@ fn invoke_value(
         Value::Command(command) =>
             invoke_command(command, this, local_arguments, context),
         Value::File(f) =>
-            if local_arguments.len() == 0 {
+            if is_executable(&f) {
+                let mut arguments = local_arguments;
+                arguments.insert(
+                    0,
+                    ArgumentDefinition::unnamed(ValueDefinition::Value(Value::File(f))));
+                invoke_command(
+                    context.env.global_static_cmd(vec!["global", "control", "cmd"])?,
+                    None,
+                    arguments,
+                    context)
+            } else if local_arguments.len() == 0 {
                 let meta = f.metadata();
                 if meta.is_ok() && meta.unwrap().is_dir() {
                     invoke_command(
@@ -188,6 +209,26 @@ fn invoke_value(
                         context),
             }
         }
+        Value::Struct(s) => {
+            match s.get("__call__") {
+                Some(Value::Command(call)) =>
+                    invoke_command(
+                        call,
+                        Some(Value::Struct(s)),
+                        local_arguments,
+                        context),
+                _ =>
+                    if local_arguments.len() == 0 {
+                        invoke_command(
+                            context.env.global_static_cmd(vec!["global", "input", "val"])?,
+                            None,
+                            vec![ArgumentDefinition::unnamed(ValueDefinition::Value(Value::Struct(s)))],
+                            context)
+                    } else {
+                        error(format!("Not a command {}", s.to_string()).as_str())
+                    }
+            }
+        }
         _ =>
             if local_arguments.len() == 0 {
                 invoke_command(
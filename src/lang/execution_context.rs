@@ -18,6 +18,8 @@ use crate::lang::job::JobJoinHandle;
 use crate::lang::binary::{BinaryReader, binary_channel};
 use std::io::Write;
 use std::fs::File;
+use num_bigint::BigInt;
+use bigdecimal::BigDecimal;
 
 pub trait ArgumentVector {
     fn check_len(&self, len: usize) -> CrushResult<()>;
@@ -318,7 +320,8 @@ pub trait This {
     fn file(self) -> CrushResult<PathBuf>;
     fn re(self) -> CrushResult<(String, Regex)>;
     fn glob(self) -> CrushResult<Glob>;
-    fn integer(self) -> CrushResult<i128>;
+    fn big_integer(self) -> CrushResult<BigInt>;
+    fn decimal(self) -> CrushResult<BigDecimal>;
     fn float(self) -> CrushResult<f64>;
     fn r#type(self) -> CrushResult<ValueType>;
     fn duration(self) -> CrushResult<Duration>;
@@ -352,14 +355,23 @@ impl This for Option<Value> {
     this_method!(table, Table, Table, "table");
     this_method!(binary, Vec<u8>, Binary, "binary");
     this_method!(glob, Glob, Glob, "glob");
-    this_method!(integer, i128, Integer, "integer");
     this_method!(float, f64, Float, "float");
+    this_method!(decimal, BigDecimal, Decimal, "decimal");
     this_method!(r#type, ValueType, Type, "type");
     this_method!(duration, Duration, Duration, "duration");
     this_method!(time, DateTime<Local>, Time, "time");
     this_method!(scope, Scope, Scope, "scope");
     this_method!(table_stream, InputStream, TableStream, "table_stream");
 
+    fn big_integer(mut self) -> CrushResult<BigInt> {
+        match self.take() {
+            Some(Value::Integer(i)) => Ok(BigInt::from(i)),
+            Some(Value::BigInteger(i)) => Ok(i),
+            None => argument_error("Expected this to be a integer, but this is not set"),
+            Some(v) => argument_error(format!("Expected this to be a integer, but it is a {}", v.value_type().to_string()).as_str()),
+        }
+    }
+
     fn re(mut self) -> CrushResult<(String, Regex)> {
         match self.take() {
             Some(Value::Regex(s, b)) => Ok((s, b)),
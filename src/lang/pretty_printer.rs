@@ -9,9 +9,11 @@ use crate::lang::table::Row;
 use crate::lang::binary::BinaryReader;
 use crate::lang::table::TableReader;
 use std::cmp::{max};
+use std::cell::RefCell;
 use std::io::{BufReader, Read};
 use crate::lang::printer::Printer;
 use crate::lang::errors::to_crush_error;
+use crate::lang::scope::Scope;
 
 pub fn create_pretty_printer(printer: Printer) -> ValueSender {
     let (o, i) = channels();
@@ -19,7 +21,11 @@ pub fn create_pretty_printer(printer: Printer) -> ValueSender {
     printer_clone.handle_error(to_crush_error(thread::Builder::new()
         .name("output-formater".to_string())
         .spawn(move || {
-            let pp = PrettyPrinter { printer };
+            /*
+              The printer runs user defined __str__ methods in a scope of its own. Holding on to the
+              global scope here would keep this thread alive forever.
+            */
+            let pp = PrettyPrinter::new(printer, Scope::create_root());
             while let Ok(val) = i.recv() {
                 pp.print_value(val);
             }
@@ -29,6 +35,9 @@ pub fn create_pretty_printer(printer: Printer) -> ValueSender {
 
 pub struct PrettyPrinter {
     printer: Printer,
+    env: Scope,
+    /* When set, lines are collected here instead of being printed. */
+    captured: Option<RefCell<Vec<String>>>,
 }
 
 fn hex(v: u8) -> String {
@@ -86,18 +95,53 @@ fn is_text(buff: &[u8]) -> bool {
 }
 
 impl PrettyPrinter {
-    pub fn new(printer: Printer) -> PrettyPrinter {
+    pub fn new(printer: Printer, env: Scope) -> PrettyPrinter {
         PrettyPrinter {
-            printer
+            printer,
+            env,
+            captured: None,
         }
     }
 
+    /**
+      A pretty printer that renders values into a string instead of printing them, see
+      into_text.
+    */
+    pub fn capture(printer: Printer, env: Scope) -> PrettyPrinter {
+        PrettyPrinter {
+            printer,
+            env,
+            captured: Some(RefCell::new(Vec::new())),
+        }
+    }
+
+    /**
+      The lines rendered by a capturing pretty printer.
+    */
+    pub fn into_text(self) -> String {
+        self.captured
+            .map(|c| c.into_inner().join("\n"))
+            .unwrap_or_default()
+    }
+
+    fn line(&self, line: &str) {
+        match &self.captured {
+            Some(c) => c.borrow_mut().push(line.to_string()),
+            None => self.printer.line(line),
+        }
+    }
+
+    fn format(&self, cell: &Value) -> String {
+        cell.to_display_string(&self.env, &self.printer)
+            .unwrap_or_else(|_| cell.to_string())
+    }
+
     pub fn print_value(&self, cell: Value) {
         match cell {
             Value::TableStream(mut output) => self.print(&mut output),
             Value::Table(rows) => self.print(&mut TableReader::new(rows)),
             Value::BinaryStream(mut b) => self.print_binary(b.as_mut(), 0),
-            _ => self.printer.line(cell.to_string().as_str()),
+            _ => self.line(self.format(&cell).as_str()),
         };
     }
 
@@ -123,7 +167,7 @@ impl PrettyPrinter {
                 }
                 Err(_) => break,
             }
-            if data.len() == self.printer.height() - 1 || has_table {
+            if (self.captured.is_none() && data.len() == self.printer.height() - 1) || has_table {
                 self.print_partial(data, stream.types(), indent, has_table);
                 data = Vec::new();
                 data.drain(..);
@@ -144,7 +188,7 @@ impl PrettyPrinter {
         for r in data {
             assert_eq!(col_count, r.cells().len());
             for (idx, c) in r.cells().iter().enumerate() {
-                let l = c.to_string().len();
+                let l = self.format(c).len();
                 w[idx] = max(w[idx], l);
             }
         }
@@ -160,7 +204,7 @@ impl PrettyPrinter {
                 header += &" ".repeat(w[idx] - val.name.len() + 1);
             }
         }
-        self.printer.line(header.as_str())
+        self.line(header.as_str())
     }
 
     fn print_row(
@@ -175,7 +219,7 @@ impl PrettyPrinter {
         let mut row = " ".repeat(indent * 4);
         let last_idx = r.len() - 1;
         for (idx, c) in r.into_vec().drain(..).enumerate() {
-            let cell = c.to_string();
+            let cell = self.format(&c);
             let spaces = if idx == cell_len - 1 { "".to_string() } else { " ".repeat(w[idx] - cell.len()) };
             let is_last = idx == last_idx;
             match c.alignment() {
@@ -202,7 +246,7 @@ impl PrettyPrinter {
                 _ => {}
             }
         }
-        self.printer.line(row.as_str());
+        self.line(row.as_str());
     }
 
     fn print_body(
@@ -253,11 +297,12 @@ impl PrettyPrinter {
                 }
             }
         }
-        self.printer.line(format_buffer(&buff[0..used], complete).as_str());
+        self.line(format_buffer(&buff[0..used], complete).as_str());
     }
 
     fn print_partial(&self, data: Vec<Row>, types: &[ColumnType], indent: usize, has_table: bool) {
-        if types.len() == 1 && indent == 0 && !has_table{
+        /* Laying out single columns side by side depends on the terminal width. */
+        if types.len() == 1 && indent == 0 && !has_table && self.captured.is_none() {
             self.print_single_column_table(data, types)
         } else {
             let mut w = vec![0; types.len()];
@@ -271,12 +316,12 @@ impl PrettyPrinter {
     }
 
     fn print_single_column_table(&self, data: Vec<Row>, types: &[ColumnType]) {
-        self.printer.line(&types[0].name);
+        self.line(&types[0].name);
         let max_width = self.printer.width();
         let mut columns = 1;
         let mut widths = vec![];
         let mut items_per_column;
-        let data = data.iter().map(|s| s.cells()[0].to_string()).collect::<Vec<_>>();
+        let data = data.iter().map(|s| self.format(&s.cells()[0])).collect::<Vec<_>>();
 
         for cols in (2..50).rev() {
             items_per_column = (data.len() - 1) / cols + 1;
@@ -303,7 +348,7 @@ impl PrettyPrinter {
                     line += &" ".repeat(widths[off] - data[idx].len() + 1);
                 }
             }
-            self.printer.line(&line);
+            self.line(&line);
         }
     }
 }
@@ -26,8 +26,14 @@ pub struct Scope {
     data: Arc<Mutex<ScopeData>>,
 }
 
+/**
+  Methods added to a builtin type from Crush code, keyed by the base type of the value.
+*/
+pub type Extensions = HashMap<ValueType, HashMap<String, Box<dyn CrushCommand + Sync + Send>>>;
+
 pub struct ScopeLoader {
     mapping: HashMap<String, Value>,
+    extensions: Extensions,
     path: Vec<String>,
     parent: Scope,
 }
@@ -69,9 +75,20 @@ impl ScopeLoader {
         Ok(())
     }
 
-    fn copy_into(&mut self, target: &mut HashMap<String, Value>) {
+    pub fn extend(&mut self, value_type: &ValueType, name: &str, method: Box<dyn CrushCommand + Sync + Send>) -> CrushResult<()> {
+        self.extensions
+            .entry(value_type.base_type())
+            .or_default()
+            .insert(name.to_string(), method);
+        Ok(())
+    }
+
+    fn copy_into(&mut self, target: &mut ScopeData) {
         for (k, v) in self.mapping.drain() {
-            target.insert(k, v);
+            target.mapping.insert(k, v);
+        }
+        for (t, methods) in self.extensions.drain() {
+            target.extensions.entry(t).or_default().extend(methods);
         }
     }
 
@@ -108,6 +125,10 @@ pub struct ScopeData {
     /** The actual data of this scope. */
     pub mapping: HashMap<String, Value>,
 
+    /** Methods that have been added to builtin types in this scope. They are visible in this
+    scope and in any scope that can see its variables. */
+    pub extensions: Extensions,
+
     /** True if this scope is a loop. Required to implement the break/continue commands.*/
     pub is_loop: bool,
 
@@ -132,6 +153,7 @@ impl ScopeData {
             is_loop,
             uses: Vec::new(),
             mapping: HashMap::new(),
+            extensions: HashMap::new(),
             is_stopped: false,
             is_readonly: false,
             name,
@@ -147,6 +169,7 @@ impl ScopeData {
             is_loop,
             uses: Vec::new(),
             mapping: HashMap::new(),
+            extensions: HashMap::new(),
             is_stopped: false,
             is_readonly: false,
             name,
@@ -164,6 +187,11 @@ impl Clone for ScopeData {
             is_loop: self.is_loop,
             uses: self.uses.clone(),
             mapping: self.mapping.clone(),
+            extensions: self.extensions.iter()
+                .map(|(t, methods)| (
+                    t.clone(),
+                    methods.iter().map(|(k, v)| (k.clone(), v.as_ref().clone())).collect()))
+                .collect(),
             is_stopped: self.is_stopped,
             is_readonly: self.is_readonly,
             name: self.name.clone(),
@@ -192,6 +220,7 @@ impl Scope {
                 calling_scope: None,
                 uses: vec![],
                 mapping: HashMap::new(),
+                extensions: HashMap::new(),
                 is_loop,
                 is_stopped,
                 is_readonly,
@@ -285,11 +314,12 @@ impl Scope {
         let loader = mandate(data.loader.take(), "Missing module loader")?;
         let mut tmp = ScopeLoader {
             mapping: HashMap::new(),
+            extensions: HashMap::new(),
             path,
             parent: data.calling_scope.as_ref().unwrap().clone(),
         };
         loader(&mut tmp)?;
-        tmp.copy_into(&mut data.mapping);
+        tmp.copy_into(&mut data);
         data.is_readonly = true;
 
         Ok(data)
@@ -371,7 +401,7 @@ impl Scope {
                                     Some(Value::Scope(s)) =>
                                         s.get_recursive(&path[1..]),
                                     Some(v) =>
-                                        v.get_recursive(&path[1..]),
+                                        v.get_recursive(&path[1..], self),
                                     _ => error(format!(
                                         "Could not find scope {} in scope {}",
                                         path[1],
@@ -492,6 +522,66 @@ impl Scope {
         }
     }
 
+    /**
+      Add a method to all values of the specified builtin type. The method is only visible from
+      inside this scope, and from scopes that can see its variables.
+    */
+    pub fn extend(&self, value_type: &ValueType, name: &str, method: Box<dyn CrushCommand + Sync + Send>) -> CrushResult<()> {
+        let mut data = self.lock()?;
+        if data.is_readonly {
+            return error("Scope is read only");
+        }
+        data.extensions
+            .entry(value_type.base_type())
+            .or_insert_with(HashMap::new)
+            .insert(name.to_string(), method);
+        Ok(())
+    }
+
+    pub fn extension(&self, value_type: &ValueType, name: &str) -> CrushResult<Option<Box<dyn CrushCommand + Sync + Send>>> {
+        let data = self.lock()?;
+        match data.extensions.get(&value_type.base_type()).and_then(|m| m.get(name)) {
+            Some(v) => Ok(Some(v.as_ref().clone())),
+            None => {
+                let uses = data.uses.clone();
+                let parent = data.parent_scope.clone();
+                drop(data);
+                for used in &uses {
+                    if let Some(res) = used.extension(value_type, name)? {
+                        return Ok(Some(res));
+                    }
+                }
+                match parent {
+                    Some(p) => p.extension(value_type, name),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    pub fn dump_extensions(&self, value_type: &ValueType, map: &mut HashMap<String, Box<dyn CrushCommand + Sync + Send>>) -> CrushResult<()> {
+        let data = self.lock()?;
+        let parent = data.parent_scope.clone();
+        let uses = data.uses.clone();
+        drop(data);
+
+        if let Some(p) = parent {
+            p.dump_extensions(value_type, map)?;
+        }
+
+        for u in uses.iter().rev() {
+            u.dump_extensions(value_type, map)?;
+        }
+
+        let data = self.lock()?;
+        if let Some(methods) = data.extensions.get(&value_type.base_type()) {
+            for (k, v) in methods.iter() {
+                map.insert(k.to_string(), v.as_ref().clone());
+            }
+        }
+        Ok(())
+    }
+
     pub fn r#use(&self, other: &Scope) {
         self.data.lock().unwrap().uses.push(other.clone());
     }
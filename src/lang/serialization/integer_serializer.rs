@@ -3,6 +3,7 @@ use crate::lang::serialization::model::{Element, element};
 use crate::lang::errors::{CrushResult, error, to_crush_error};
 use crate::lang::value::Value;
 use std::convert::TryFrom;
+use num_bigint::BigInt;
 
 impl Serializable<i128> for i128 {
     fn deserialize(id: usize, elements: &[Element], _state: &mut DeserializationState) -> CrushResult<i128> {
@@ -25,3 +26,22 @@ impl Serializable<i128> for i128 {
         Ok(idx)
     }
 }
+
+impl Serializable<BigInt> for BigInt {
+    fn deserialize(id: usize, elements: &[Element], _state: &mut DeserializationState) -> CrushResult<BigInt> {
+        match elements[id].element.as_ref().unwrap() {
+            element::Element::SmallInteger(i) => Ok(BigInt::from(*i)),
+            element::Element::LargeInteger(s) => Ok(to_crush_error(s.parse())?),
+            _ => error("Expected integer"),
+        }
+    }
+
+    fn serialize(&self, elements: &mut Vec<Element>, state: &mut SerializationState) -> CrushResult<usize> {
+        let idx = elements.len();
+        state.values.insert(Value::big_integer(self.clone()), idx);
+        elements.push(Element {
+            element: Some(element::Element::LargeInteger(self.to_string())),
+        });
+        Ok(idx)
+    }
+}
@@ -17,6 +17,9 @@ use std::os::unix::ffi::OsStrExt;
 use chrono::offset::TimeZone;
 use crate::lang::dict::Dict;
 use crate::lang::scope::Scope;
+use num_bigint::BigInt;
+use bigdecimal::BigDecimal;
+use std::str::FromStr;
 
 fn serialize_simple(value: &Value, elements: &mut Vec<Element>, state: &mut SerializationState) -> CrushResult<usize> {
     let idx = elements.len();
@@ -29,6 +32,7 @@ fn serialize_simple(value: &Value, elements: &mut Vec<Element>, state: &mut Seri
             Value::File(b) => element::Element::File(b.as_os_str().to_os_string().into_vec()),
             Value::Binary(b) => element::Element::Binary(b.clone()),
             Value::Float(f) => element::Element::Float(*f),
+            Value::Decimal(d) => element::Element::Decimal(d.to_string()),
             Value::Bool(b) => element::Element::Bool(*b),
             Value::Empty() => element::Element::Empty(false),
             Value::Time(d) => element::Element::Time(d.timestamp_nanos()),
@@ -45,6 +49,7 @@ impl Serializable<Value> for Value {
             element::Element::File(f) => Ok(Value::File(
                 PathBuf::from(OsStr::from_bytes(&f[..])))),
             element::Element::Float(v) => Ok(Value::Float(*v)),
+            element::Element::Decimal(v) => Ok(Value::Decimal(to_crush_error(BigDecimal::from_str(v))?)),
             element::Element::Binary(v) => Ok(Value::Binary(v.clone())),
             element::Element::Glob(v) => Ok(Value::Glob(Glob::new(v))),
             element::Element::Regex(v) => Ok(Value::Regex(
@@ -55,7 +60,7 @@ impl Serializable<Value> for Value {
             element::Element::Empty(_) => Ok(Value::Empty()),
 
             element::Element::SmallInteger(_) | element::Element::LargeInteger(_) =>
-                Ok(Value::Integer(i128::deserialize(id, elements, state)?)),
+                Ok(Value::big_integer(BigInt::deserialize(id, elements, state)?)),
 
             element::Element::Duration(d) =>
                 Ok(Value::Duration(
@@ -89,10 +94,11 @@ impl Serializable<Value> for Value {
 
         match self {
             Value::String(_) | Value::Glob(_) | Value::Regex(_, _) | Value::File(_) |
-            Value::Binary(_) | Value::Float(_) | Value::Bool(_) | Value::Empty() |
+            Value::Binary(_) | Value::Float(_) | Value::Decimal(_) | Value::Bool(_) | Value::Empty() |
             Value::Time(_) => serialize_simple(self, elements, state),
 
             Value::Integer(s) => s.serialize(elements, state),
+            Value::BigInteger(s) => s.serialize(elements, state),
 
             Value::Duration(d) => {
                 let mut node = Element::default();
@@ -30,6 +30,7 @@ impl Serializable<ValueType> for ValueType {
                         14 => ValueType::Time,
                         15 => ValueType::Struct,
                         16 => ValueType::Any,
+                        18 => ValueType::Decimal,
                         _ => return error("Unrecognised type")
                     })
                 }
@@ -75,6 +76,7 @@ impl Serializable<ValueType> for ValueType {
             ValueType::Scope => SimpleTypeKind::Scope,
             ValueType::Bool => SimpleTypeKind::Bool,
             ValueType::Float => SimpleTypeKind::Float,
+            ValueType::Decimal => SimpleTypeKind::Decimal,
             ValueType::Empty => SimpleTypeKind::Empty,
             ValueType::Any => SimpleTypeKind::Any,
             ValueType::Binary => SimpleTypeKind::Binary,
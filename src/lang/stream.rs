@@ -19,9 +19,22 @@ pub fn black_hole() -> ValueSender {
 #[derive(Clone)]
 pub struct ValueSender {
     sender: Sender<Value>,
+    is_terminal: bool,
 }
 
 impl ValueSender {
+    /**
+      Mark this sender as the output of an interactive session, i.e. anything sent to it is
+      displayed directly on the terminal.
+    */
+    pub fn terminal(self) -> ValueSender {
+        ValueSender { sender: self.sender, is_terminal: true }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.is_terminal
+    }
+
     pub fn send(&self, cell: Value) -> CrushResult<()> {
         match self.sender.send(cell) {
             Ok(_) => Ok(()),
@@ -102,13 +115,14 @@ impl InputStream {
                 if row.cells().len() != self.types.len() {
                     return error("Wrong number of columns in input");
                 }
+                /* Empty cells are missing values, which any column may have. */
                 for (c, ct) in row.cells().iter().zip(self.types.iter()) {
-                    if c.value_type() != ct.cell_type {
+                    if !ct.cell_type.is(c) && !matches!(c, Value::Empty()) {
                         return error(format!(
                             "Wrong cell type in input column {:?}, expected {:?}, got {:?}",
                             ct.name,
-                            c.value_type(),
-                            ct.cell_type).as_str());
+                            ct.cell_type,
+                            c.value_type()).as_str());
                     }
                 }
                 res
@@ -120,7 +134,7 @@ impl InputStream {
 
 pub fn channels() -> (ValueSender, ValueReceiver) {
     let (send, recv) = bounded(1);
-    (ValueSender {sender: send}, ValueReceiver { receiver: recv })
+    (ValueSender {sender: send, is_terminal: false}, ValueReceiver { receiver: recv })
 }
 
 pub fn streams(signature: Vec<ColumnType>) -> (OutputStream, InputStream) {
@@ -156,3 +170,34 @@ impl Readable for InputStream {
         self.types()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::value::ValueType;
+
+    #[test]
+    fn typed_columns_reject_other_cell_types() {
+        let (output, input) = streams(vec![ColumnType::new("a", ValueType::Integer)]);
+        output.send(Row::new(vec![Value::Integer(1)])).unwrap();
+        output.send(Row::new(vec![Value::string("x")])).unwrap();
+        assert!(input.recv().is_ok());
+        assert!(input.recv().is_err());
+    }
+
+    #[test]
+    fn typed_columns_accept_empty_cells() {
+        let (output, input) = streams(vec![ColumnType::new("a", ValueType::Integer)]);
+        output.send(Row::new(vec![Value::Empty()])).unwrap();
+        assert!(input.recv().is_ok());
+    }
+
+    #[test]
+    fn any_columns_accept_all_cell_types() {
+        let (output, input) = streams(vec![ColumnType::new("a", ValueType::Any)]);
+        output.send(Row::new(vec![Value::Integer(1)])).unwrap();
+        output.send(Row::new(vec![Value::string("x")])).unwrap();
+        assert!(input.recv().is_ok());
+        assert!(input.recv().is_ok());
+    }
+}
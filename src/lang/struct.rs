@@ -7,6 +7,12 @@ use std::hash::{Hash, Hasher};
 use std::cmp::Ordering;
 use crate::util::replace::Replace;
 use crate::util::identity_arc::Identity;
+use crate::lang::argument::Argument;
+use crate::lang::scope::Scope;
+use crate::lang::printer::Printer;
+use crate::lang::errors::CrushResult;
+use crate::lang::stream::{channels, empty_channel};
+use crate::lang::execution_context::ExecutionContext;
 
 #[derive(Clone)]
 struct StructData {
@@ -132,10 +138,6 @@ impl Struct {
         Row::new(self.data.lock().unwrap().cells.clone())
     }
 
-    pub fn to_vec(&self) -> Vec<Value> {
-        self.data.lock().unwrap().cells.clone()
-    }
-
     pub fn get(&self, name: &str) -> Option<Value> {
         let data = self.data.lock().unwrap();
         match data.lookup.get(name) {
@@ -194,6 +196,40 @@ impl Struct {
     pub fn set_parent(&self, parent: Option<Struct>) {
         self.data.lock().unwrap().parent = parent;
     }
+
+    /**
+      Call the method with the specified name on this struct. This is used to let builtins like
+      comparisons, sorting and printing defer to user defined methods like `__eq__` or `__str__`.
+
+      Returns None if the struct has no such method, or if the method did not produce any output.
+    */
+    pub fn call_method(
+        &self,
+        name: &str,
+        arguments: Vec<Argument>,
+        env: &Scope,
+        printer: &Printer,
+    ) -> CrushResult<Option<Value>> {
+        match self.get(name) {
+            Some(Value::Command(command)) => {
+                let (sender, receiver) = channels();
+                command.invoke(ExecutionContext {
+                    input: empty_channel(),
+                    output: sender,
+                    arguments,
+                    env: env.clone(),
+                    this: Some(Value::Struct(self.clone())),
+                    printer: printer.clone(),
+                })?;
+                Ok(receiver.recv().ok())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn has_method(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(Value::Command(_)))
+    }
 }
 
 impl ToString for Struct {
@@ -31,10 +31,20 @@ use crate::lang::pretty_printer::format_buffer;
 use crate::util::regex::RegexFileMatcher;
 use crate::lang::printer::Printer;
 use crate::lang::help::Help;
+use crate::lang::argument::Argument;
+use num_bigint::BigInt;
+use bigdecimal::BigDecimal;
+use num_traits::ToPrimitive;
 
 pub enum Value {
     String(String),
     Integer(i128),
+    /**
+      An integer too large to fit in an i128. The type of these values is also integer, and
+      arithmetic transparently moves between the two representations. Values that fit in an i128
+      are always stored as an Integer, so that equality and hashing can compare representations.
+    */
+    BigInteger(BigInt),
     Time(DateTime<Local>),
     Duration(Duration),
     Field(Vec<String>),
@@ -50,6 +60,8 @@ pub enum Value {
     Scope(Scope),
     Bool(bool),
     Float(f64),
+    /** An exact decimal number, used e.g. for monetary values. */
+    Decimal(BigDecimal),
     Empty(),
     BinaryStream(Box<dyn BinaryReader + Send + Sync>),
     Binary(Vec<u8>),
@@ -61,6 +73,7 @@ impl ToString for Value {
         match self {
             Value::String(val) => val.to_string(),
             Value::Integer(val) => val.to_string(),
+            Value::BigInteger(val) => val.to_string(),
             Value::Time(val) => val.format("%Y-%m-%d %H:%M:%S %z").to_string(),
             Value::Field(val) => format!(r"^{}", val.join(":")),
             Value::Glob(val) => val.to_string(),
@@ -72,6 +85,7 @@ impl ToString for Value {
             Value::Bool(v) => (if *v { "true" } else { "false" }).to_string(),
             Value::Dict(d) => d.to_string(),
             Value::Float(f) => f.to_string(),
+            Value::Decimal(d) => d.to_string(),
             Value::Binary(v) => format_buffer(v, true),
             Value::Type(t) => t.to_string(),
             Value::Struct(s) => s.to_string(),
@@ -92,8 +106,12 @@ impl Value {
         }
     }
 
-    pub fn field(&self, name: &str) -> CrushResult<Option<Value>> {
-        Ok(match self {
+    /**
+      Look up a member of this value. Builtin methods take precedence, after which methods added to
+      the type of the value in the specified scope using `types:extend` are considered.
+    */
+    pub fn field(&self, name: &str, env: &Scope) -> CrushResult<Option<Value>> {
+        let res = match self {
             Value::Struct(s) => s.get(name),
             Value::Scope(subenv) =>
                 subenv
@@ -113,33 +131,55 @@ impl Value {
                     .fields()
                     .get(name)
                     .map(|m| Value::Command(m.as_ref().clone())),
-        })
+        };
+        match res {
+            Some(v) => Ok(Some(v)),
+            None => {
+                let value_type = match self {
+                    Value::Type(t) => t.clone(),
+                    v => v.value_type(),
+                };
+                Ok(env.extension(&value_type, name)?.map(Value::Command))
+            }
+        }
     }
 
-    pub fn fields(&self) -> Vec<String> {
+    pub fn fields(&self, env: &Scope) -> CrushResult<Vec<String>> {
         let mut res = Vec::new();
-        match self {
+        let value_type = match self {
             Value::Struct(s) => {
-                res.append(&mut s.keys())
+                res.append(&mut s.keys());
+                ValueType::Struct
             }
 //            Value::Scope(subenv) => subenv.get(name),
             Value::Type(t) => {
-                add_keys(t.fields(), &mut res)
+                add_keys(t.fields(), &mut res);
+                t.clone()
+            }
+            _ => {
+                add_keys(self.value_type().fields(), &mut res);
+                self.value_type()
+            }
+        };
+        let mut extensions = HashMap::new();
+        env.dump_extensions(&value_type, &mut extensions)?;
+        for k in extensions.keys() {
+            if !res.contains(k) {
+                res.push(k.to_string());
             }
-            _ => add_keys(self.value_type().fields(), &mut res),
         }
         res.sort_by(|x, y| x.cmp(y));
 
-        res
+        Ok(res)
     }
 
-    pub fn get_recursive(&self, path: &[String]) -> CrushResult<Value> {
+    pub fn get_recursive(&self, path: &[String], env: &Scope) -> CrushResult<Value> {
         match path.len() {
             0 =>
                 error("Invalid path"),
             1 => Ok(self.clone()),
-            2 => mandate(self.field(&path[1])?, "Invalid path"),
-            _ => mandate(self.field(&path[1])?, "Invalid path")?.get_recursive(&path[1..]),
+            2 => mandate(self.field(&path[1], env)?, "Invalid path"),
+            _ => mandate(self.field(&path[1], env)?, "Invalid path")?.get_recursive(&path[1..], env),
         }
     }
 
@@ -152,7 +192,7 @@ impl Value {
 
     pub fn alignment(&self) -> Alignment {
         match self {
-            Value::Time(_) | Value::Duration(_) | Value::Integer(_) => Alignment::Right,
+            Value::Time(_) | Value::Duration(_) | Value::Integer(_) | Value::BigInteger(_) | Value::Decimal(_) => Alignment::Right,
             _ => Alignment::Left,
         }
     }
@@ -166,6 +206,26 @@ impl Value {
         Value::String(s.to_string())
     }
 
+    /**
+      Create an integer value, using the small representation if the number fits.
+    */
+    pub fn big_integer(i: BigInt) -> Value {
+        match i.to_i128() {
+            Some(small) => Value::Integer(small),
+            None => Value::BigInteger(i),
+        }
+    }
+
+    /**
+      Parse an integer of any size.
+    */
+    pub fn parse_integer(s: &str) -> CrushResult<Value> {
+        match s.parse::<i128>() {
+            Ok(i) => Ok(Value::Integer(i)),
+            Err(_) => Ok(Value::big_integer(to_crush_error(BigInt::from_str(s))?)),
+        }
+    }
+
     pub fn readable(&self) -> Option<Box<dyn Readable>> {
         match self {
             Value::TableStream(s) => Some(Box::from(s.clone())),
@@ -179,7 +239,7 @@ impl Value {
     pub fn value_type(&self) -> ValueType {
         match self {
             Value::String(_) => ValueType::String,
-            Value::Integer(_) => ValueType::Integer,
+            Value::Integer(_) | Value::BigInteger(_) => ValueType::Integer,
             Value::Time(_) => ValueType::Time,
             Value::Field(_) => ValueType::Field,
             Value::Glob(_) => ValueType::Glob,
@@ -195,6 +255,7 @@ impl Value {
             Value::Bool(_) => ValueType::Bool,
             Value::Dict(d) => d.dict_type(),
             Value::Float(_) => ValueType::Float,
+            Value::Decimal(_) => ValueType::Decimal,
             Value::Empty() => ValueType::Empty,
             Value::BinaryStream(_) => ValueType::BinaryStream,
             Value::Binary(_) => ValueType::Binary,
@@ -251,6 +312,50 @@ impl Value {
         }
     }
 
+    /**
+      Convert this value to a string, using the `__str__` method of structs that define one.
+    */
+    pub fn to_display_string(&self, env: &Scope, printer: &Printer) -> CrushResult<String> {
+        match self {
+            Value::Struct(s) => match s.call_method("__str__", vec![], env, printer)? {
+                Some(Value::String(res)) => Ok(res),
+                Some(v) => Ok(v.to_string()),
+                None => Ok(self.to_string()),
+            },
+            _ => Ok(self.to_string()),
+        }
+    }
+
+    /**
+      Check two values for equality, using the `__eq__` method of structs that define one.
+    */
+    pub fn equals(&self, other: &Value, env: &Scope, printer: &Printer) -> CrushResult<bool> {
+        match self {
+            Value::Struct(s) if s.has_method("__eq__") =>
+                call_predicate(s, "__eq__", other, env, printer),
+            _ => Ok(self == other),
+        }
+    }
+
+    /**
+      Compare two values, using the `__lt__` method of structs that define one.
+    */
+    pub fn compare(&self, other: &Value, env: &Scope, printer: &Printer) -> CrushResult<Option<Ordering>> {
+        match (self, other) {
+            (Value::Struct(s), _) if s.has_method("__lt__") => {
+                if call_predicate(s, "__lt__", other, env, printer)? {
+                    return Ok(Some(Ordering::Less));
+                }
+                match other {
+                    Value::Struct(o) if o.has_method("__lt__") && call_predicate(o, "__lt__", self, env, printer)? =>
+                        Ok(Some(Ordering::Greater)),
+                    _ => Ok(Some(Ordering::Equal)),
+                }
+            }
+            _ => Ok(self.partial_cmp(other)),
+        }
+    }
+
     pub fn cast(self, new_type: ValueType) -> CrushResult<Value> {
         if self.value_type() == new_type {
             return Ok(self);
@@ -259,6 +364,14 @@ impl Value {
         match (&self, &new_type) {
             (Value::Integer(i), ValueType::Bool) =>
                 return Ok(Value::Bool(*i != 0)),
+            (Value::BigInteger(_), ValueType::Bool) =>
+                return Ok(Value::Bool(true)),
+            (Value::BigInteger(i), ValueType::Float) =>
+                return Ok(Value::Float(mandate(i.to_f64(), "Integer out of range")?)),
+            (Value::Decimal(d), ValueType::Integer) =>
+                return Ok(Value::big_integer(d.with_scale(0).into_bigint_and_exponent().0)),
+            (Value::Decimal(d), ValueType::Float) =>
+                return Ok(Value::Float(mandate(d.to_f64(), "Decimal out of range")?)),
             (Value::Float(f), ValueType::Integer) =>
                 return Ok(Value::Integer(*f as i128)),
             _ => {}
@@ -269,11 +382,12 @@ impl Value {
         match new_type {
             ValueType::File => Ok(Value::File(PathBuf::from(str_val.as_str()))),
             ValueType::Glob => Ok(Value::Glob(Glob::new(str_val.as_str()))),
-            ValueType::Integer => to_crush_error(str_val.parse::<i128>()).map(Value::Integer),
+            ValueType::Integer => Value::parse_integer(&str_val),
             ValueType::Field => Ok(Value::Field(vec![str_val])),
             ValueType::Regex => to_crush_error(Regex::new(str_val.as_str()).map(|v| Value::Regex(str_val, v))),
             ValueType::Binary => Ok(Value::Binary(str_val.bytes().collect())),
             ValueType::Float => Ok(Value::Float(to_crush_error(f64::from_str(&str_val))?)),
+            ValueType::Decimal => Ok(Value::Decimal(to_crush_error(BigDecimal::from_str(&str_val))?)),
             ValueType::Bool => Ok(Value::Bool(match str_val.as_str() {
                 "true" => true,
                 "false" => false,
@@ -302,6 +416,7 @@ impl Clone for Value {
         match self {
             Value::String(v) => Value::String(v.clone()),
             Value::Integer(v) => Value::Integer(*v),
+            Value::BigInteger(v) => Value::BigInteger(v.clone()),
             Value::Time(v) => Value::Time(*v),
             Value::Field(v) => Value::Field(v.clone()),
             Value::Glob(v) => Value::Glob(v.clone()),
@@ -317,6 +432,7 @@ impl Clone for Value {
             Value::Bool(v) => Value::Bool(*v),
             Value::Dict(d) => Value::Dict(d.clone()),
             Value::Float(f) => Value::Float(*f),
+            Value::Decimal(d) => Value::Decimal(d.clone()),
             Value::Empty() => Value::Empty(),
             Value::BinaryStream(v) => Value::BinaryStream(v.as_ref().clone()),
             Value::Binary(v) => Value::Binary(v.clone()),
@@ -325,6 +441,13 @@ impl Clone for Value {
     }
 }
 
+fn call_predicate(s: &Struct, name: &str, other: &Value, env: &Scope, printer: &Printer) -> CrushResult<bool> {
+    match s.call_method(name, vec![Argument::unnamed(other.clone())], env, printer)? {
+        Some(Value::Bool(res)) => Ok(res),
+        _ => error(format!("Expected method {} to return a boolean", name).as_str()),
+    }
+}
+
 fn integer_decode(val: f64) -> (u64, i16, i8) {
     let bits: u64 = unsafe { std::mem::transmute(val) };
     let sign: i8 = if bits >> 63 == 0 { 1 } else { -1 };
@@ -347,6 +470,7 @@ impl std::hash::Hash for Value {
         match self {
             Value::String(v) => v.hash(state),
             Value::Integer(v) => v.hash(state),
+            Value::BigInteger(v) => v.hash(state),
             Value::Time(v) => v.hash(state),
             Value::Field(v) => v.hash(state),
             Value::Glob(v) => v.hash(state),
@@ -366,6 +490,7 @@ impl std::hash::Hash for Value {
                 x.hash(state);
                 s.hash(state);
             }
+            Value::Decimal(v) => v.hash(state),
             Value::Empty() => {}
             Value::Type(v) => v.to_string().hash(state),
         }
@@ -384,6 +509,7 @@ impl std::cmp::PartialEq for Value {
         match (self, other) {
             (Value::String(val1), Value::String(val2)) => val1 == val2,
             (Value::Integer(val1), Value::Integer(val2)) => val1 == val2,
+            (Value::BigInteger(val1), Value::BigInteger(val2)) => val1 == val2,
             (Value::Time(val1), Value::Time(val2)) => val1 == val2,
             (Value::Duration(val1), Value::Duration(val2)) => val1 == val2,
             (Value::Field(val1), Value::Field(val2)) => val1 == val2,
@@ -399,6 +525,11 @@ impl std::cmp::PartialEq for Value {
             (Value::Dict(val1), Value::Dict(val2)) => val1 == val2,
             (Value::Bool(val1), Value::Bool(val2)) => val1 == val2,
             (Value::Float(val1), Value::Float(val2)) => val1 == val2,
+            (Value::Decimal(val1), Value::Decimal(val2)) => val1 == val2,
+            (Value::Decimal(_), Value::Integer(_)) | (Value::Decimal(_), Value::BigInteger(_)) |
+            (Value::Decimal(_), Value::Float(_)) | (Value::Integer(_), Value::Decimal(_)) |
+            (Value::BigInteger(_), Value::Decimal(_)) | (Value::Float(_), Value::Decimal(_)) =>
+                self.partial_cmp(other) == Some(Ordering::Equal),
             (Value::Binary(val1), Value::Binary(val2)) => val1 == val2,
             _ => false,
         }
@@ -410,8 +541,29 @@ pub enum Alignment {
     Right,
 }
 
+fn compare_float(f: f64, d: &BigDecimal) -> Option<Ordering> {
+    if f.is_nan() {
+        None
+    } else if f.is_infinite() {
+        Some(if f > 0.0 { Ordering::Greater } else { Ordering::Less })
+    } else {
+        Some(BigDecimal::from(f).cmp(d))
+    }
+}
+
 impl std::cmp::PartialOrd for Value {
     fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        /* Decimals can be compared with the other numeric types, like they can be added to them. */
+        match (self, other) {
+            (Value::Decimal(val1), Value::Integer(val2)) => return Some(val1.cmp(&BigDecimal::new(BigInt::from(*val2), 0))),
+            (Value::Integer(val1), Value::Decimal(val2)) => return Some(BigDecimal::new(BigInt::from(*val1), 0).cmp(val2)),
+            (Value::Decimal(val1), Value::BigInteger(val2)) => return Some(val1.cmp(&BigDecimal::new(val2.clone(), 0))),
+            (Value::BigInteger(val1), Value::Decimal(val2)) => return Some(BigDecimal::new(val1.clone(), 0).cmp(val2)),
+            (Value::Decimal(val1), Value::Float(val2)) => return compare_float(*val2, val1).map(Ordering::reverse),
+            (Value::Float(val1), Value::Decimal(val2)) => return compare_float(*val1, val2),
+            _ => {}
+        }
+
         let t1 = self.value_type();
         let t2 = other.value_type();
         if t1 != t2 {
@@ -421,6 +573,9 @@ impl std::cmp::PartialOrd for Value {
         match (self, other) {
             (Value::String(val1), Value::String(val2)) => Some(val1.cmp(val2)),
             (Value::Integer(val1), Value::Integer(val2)) => Some(val1.cmp(val2)),
+            (Value::BigInteger(val1), Value::BigInteger(val2)) => Some(val1.cmp(val2)),
+            (Value::Integer(val1), Value::BigInteger(val2)) => Some(BigInt::from(*val1).cmp(val2)),
+            (Value::BigInteger(val1), Value::Integer(val2)) => Some(val1.cmp(&BigInt::from(*val2))),
             (Value::Time(val1), Value::Time(val2)) => Some(val1.cmp(val2)),
             (Value::Duration(val1), Value::Duration(val2)) => Some(val1.cmp(val2)),
             (Value::Field(val1), Value::Field(val2)) => Some(val1.cmp(val2)),
@@ -433,6 +588,7 @@ impl std::cmp::PartialOrd for Value {
             (Value::Dict(val1), Value::Dict(val2)) => val1.partial_cmp(val2),
             (Value::Bool(val1), Value::Bool(val2)) => Some(val1.cmp(val2)),
             (Value::Float(val1), Value::Float(val2)) => val1.partial_cmp(val2),
+            (Value::Decimal(val1), Value::Decimal(val2)) => Some(val1.cmp(val2)),
             (Value::Binary(val1), Value::Binary(val2)) => Some(val1.cmp(val2)),
             _ => None,
         }
@@ -471,6 +627,16 @@ impl Help for Value {
 mod tests {
     use super::*;
 
+    #[test]
+    fn decimal_comparisons() {
+        let d = Value::Decimal(BigDecimal::from_str("1.5").unwrap());
+        assert_eq!(d.partial_cmp(&Value::Integer(1)), Some(Ordering::Greater));
+        assert_eq!(Value::Integer(2).partial_cmp(&d), Some(Ordering::Greater));
+        assert_eq!(d.partial_cmp(&Value::Float(1.5)), Some(Ordering::Equal));
+        assert_eq!(Value::Float(1.25).partial_cmp(&d), Some(Ordering::Less));
+        assert_eq!(d.partial_cmp(&Value::Float(f64::NAN)), None);
+    }
+
     #[test]
     fn text_casts() {
         assert_eq!(Value::string("112432").cast(ValueType::Integer).is_err(), false);
@@ -481,6 +647,15 @@ mod tests {
         assert_eq!(Value::string("fad").cast(ValueType::Field).is_err(), false);
     }
 
+    #[test]
+    fn big_integers() {
+        let big = Value::string("1000000000000000000000000000000000000000000").cast(ValueType::Integer).unwrap();
+        assert_eq!(big.value_type(), ValueType::Integer);
+        assert_eq!(big.partial_cmp(&Value::Integer(5)), Some(Ordering::Greater));
+        assert_eq!(Value::Integer(5).partial_cmp(&big), Some(Ordering::Less));
+        assert_eq!(Value::big_integer(BigInt::from(17)) == Value::Integer(17), true);
+    }
+
     #[test]
     fn test_duration_format() {
         assert_eq!(duration_format(&Duration::microseconds(0)), "0".to_string());
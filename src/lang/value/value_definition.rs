@@ -9,6 +9,10 @@ use crate::lang::errors::{block_error, mandate};
 use crate::lang::execution_context::CompileContext;
 use std::path::PathBuf;
 use crate::lang::command::Parameter;
+use crate::lang::value::ValueType;
+use crate::lang::list::List;
+use crate::lang::dict::Dict;
+use crate::lang::errors::error;
 
 #[derive(Clone)]
 pub enum ValueDefinition {
@@ -18,6 +22,8 @@ pub enum ValueDefinition {
     Label(String),
     GetAttr(Box<ValueDefinition>, String),
     Path(Box<ValueDefinition>, String),
+    List(Vec<ValueDefinition>),
+    Dict(Vec<(ValueDefinition, ValueDefinition)>),
 }
 
 fn file_get(f: &str) -> Option<Value> {
@@ -29,11 +35,35 @@ fn file_get(f: &str) -> Option<Value> {
     }
 }
 
+/**
+  The common type of all the specified values, or any if they are of different types.
+*/
+fn common_type(values: &[Value]) -> ValueType {
+    match values.first() {
+        None => ValueType::Any,
+        Some(first) => {
+            let t = first.value_type();
+            if values.iter().all(|v| v.value_type() == t) {
+                t
+            } else {
+                ValueType::Any
+            }
+        }
+    }
+}
+
 impl ValueDefinition {
     pub fn can_block(&self, _arg: &[ArgumentDefinition], context: &mut CompileContext) -> bool {
+        self.value_can_block(context)
+    }
+
+    fn value_can_block(&self, context: &mut CompileContext) -> bool {
         match self {
             ValueDefinition::JobDefinition(j) => j.can_block(context),
             ValueDefinition::GetAttr(_inner1, _inner2) => true,
+            ValueDefinition::List(l) => l.iter().any(|e| e.value_can_block(context)),
+            ValueDefinition::Dict(d) =>
+                d.iter().any(|(k, v)| k.value_can_block(context) || v.value_can_block(context)),
             _ => false,
         }
     }
@@ -71,7 +101,7 @@ impl ValueDefinition {
             ValueDefinition::GetAttr(parent_def, entry) => {
                 let parent = parent_def.compile_internal(context, can_block)?.1;
                 let val = mandate(
-                    parent.field(&entry)?,
+                    parent.field(&entry, &context.env)?,
                     format!("Missing field {} in value of type {}", entry, parent.value_type().to_string()).as_str())?;
                 (Some(parent), val)
             }
@@ -81,8 +111,38 @@ impl ValueDefinition {
                 let val = mandate(parent.path(&entry), format!("Missing path entry {} in {}", entry, parent_def.to_string()).as_str())?;
                 (Some(parent), val)
             }
+
+            ValueDefinition::List(elements) => {
+                let values = elements.iter()
+                    .map(|e| e.compile_element(context, can_block))
+                    .collect::<CrushResult<Vec<_>>>()?;
+                (None, Value::List(List::new(common_type(&values), values)))
+            }
+
+            ValueDefinition::Dict(entries) => {
+                let mut keys = Vec::new();
+                let mut values = Vec::new();
+                for (k, v) in entries {
+                    keys.push(k.compile_element(context, can_block)?);
+                    values.push(v.compile_element(context, can_block)?);
+                }
+                let key_type = common_type(&keys);
+                if keys.iter().any(|k| !k.value_type().is_hashable()) {
+                    return error("Unhashable dict key");
+                }
+                let dict = Dict::new(key_type, common_type(&values));
+                for (k, v) in keys.drain(..).zip(values.drain(..)) {
+                    dict.insert(k, v)?;
+                }
+                (None, Value::Dict(dict))
+            }
         })
     }
+
+    fn compile_element(&self, context: &mut CompileContext, can_block: bool) -> CrushResult<Value> {
+        let (t, v) = self.compile_internal(context, can_block)?;
+        Ok(t.map(|tt| v.clone().bind(tt)).unwrap_or(v))
+    }
 }
 
 impl ToString for ValueDefinition {
@@ -94,6 +154,13 @@ impl ToString for ValueDefinition {
             ValueDefinition::JobDefinition(_) => "<job>".to_string(),
             ValueDefinition::GetAttr(v, l) => format!("{}:{}", v.to_string(), l),
             ValueDefinition::Path(v, l) => format!("{}/{}", v.to_string(), l),
+            ValueDefinition::List(l) => format!(
+                "$[{}]",
+                l.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ")),
+            ValueDefinition::Dict(d) if d.is_empty() => "$[=>]".to_string(),
+            ValueDefinition::Dict(d) => format!(
+                "$[{}]",
+                d.iter().map(|(k, v)| format!("{} => {}", k.to_string(), v.to_string())).collect::<Vec<_>>().join(" ")),
         }
     }
 }
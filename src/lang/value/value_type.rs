@@ -9,6 +9,8 @@ use crate::lib::types;
 use lazy_static::lazy_static;
 use crate::lang::help::Help;
 use std::cmp::max;
+use crate::lang::scope::Scope;
+use bigdecimal::BigDecimal;
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub enum ValueType {
@@ -29,6 +31,7 @@ pub enum ValueType {
     Scope,
     Bool,
     Float,
+    Decimal,
     Empty,
     Any,
     BinaryStream,
@@ -61,6 +64,8 @@ impl ValueType {
                 &types::integer::METHODS,
             ValueType::Float =>
                 &types::float::METHODS,
+            ValueType::Decimal =>
+                &types::decimal::METHODS,
             ValueType::Duration =>
                 &types::duration::METHODS,
             ValueType::Time =>
@@ -78,6 +83,38 @@ impl ValueType {
     }
 
 
+    /**
+      The type without any type parameters. Methods are shared between e.g. all list types, so this
+      is the type that methods added through `types:extend` are registered on.
+    */
+    pub fn base_type(&self) -> ValueType {
+        match self {
+            ValueType::List(_) => ValueType::List(Box::from(ValueType::Any)),
+            ValueType::Dict(_, _) => ValueType::Dict(Box::from(ValueType::Any), Box::from(ValueType::Any)),
+            ValueType::Table(_) => ValueType::Table(vec![]),
+            ValueType::TableStream(_) => ValueType::TableStream(vec![]),
+            t => t.clone(),
+        }
+    }
+
+    /**
+      The long help of this type, including any methods that have been added to it in the
+      specified scope.
+    */
+    pub fn long_help_in(&self, env: &Scope) -> CrushResult<Option<String>> {
+        let mut extensions = HashMap::new();
+        env.dump_extensions(self, &mut extensions)?;
+
+        let mut lines = Vec::new();
+        let mut keys: Vec<_> = self.fields().iter()
+            .chain(extensions.iter().filter(|(k, _)| !self.fields().contains_key(*k)))
+            .collect();
+        keys.sort_by(|x, y| x.0.cmp(&y.0));
+
+        long_help_methods(&keys, &mut lines);
+        Ok(Some(lines.join("\n")))
+    }
+
     pub fn is(&self, value: &Value) -> bool {
         (*self == ValueType::Any) || (*self == value.value_type())
     }
@@ -87,7 +124,7 @@ impl ValueType {
             ValueType::String | ValueType::Integer | ValueType::Time |
             ValueType::Duration | ValueType::Field | ValueType::Glob |
             ValueType::Regex | ValueType::Command | ValueType::File |
-            ValueType::Scope | ValueType::Float | ValueType::Empty |
+            ValueType::Scope | ValueType::Float | ValueType::Decimal | ValueType::Empty |
             ValueType::Any | ValueType::Binary | ValueType::Type |
             ValueType::Struct | ValueType::Bool => self.clone(),
             ValueType::BinaryStream => ValueType::Binary,
@@ -119,15 +156,13 @@ impl ValueType {
     pub fn parse(&self, s: &str) -> CrushResult<Value> {
         match self {
             ValueType::String => Ok(Value::string(s)),
-            ValueType::Integer => match s.parse::<i128>() {
-                Ok(n) => Ok(Value::Integer(n)),
-                Err(e) => error(e.to_string().as_str()),
-            }
+            ValueType::Integer => Value::parse_integer(s),
             ValueType::Field => Ok(Value::Field(mandate(parse_name(s), "Invalid field name")?)),
             ValueType::Glob => Ok(Value::Glob(Glob::new(s))),
             ValueType::Regex => Ok(Value::Regex(s.to_string(), to_crush_error(Regex::new(s))?)),
             ValueType::File => Ok(Value::string(s)),
             ValueType::Float => Ok(Value::Float(to_crush_error(s.parse::<f64>())?)),
+            ValueType::Decimal => Ok(Value::Decimal(to_crush_error(s.parse::<BigDecimal>())?)),
             ValueType::Bool => Ok(Value::Bool(to_crush_error(s.parse::<bool>())?)),
             _ => error("Failed to parse cell"),
         }
@@ -158,6 +193,7 @@ impl Help for ValueType {
             ValueType::Scope => "A scope in the Crush namespace",
             ValueType::Bool => "True or false",
             ValueType::Float => "A numeric type representing any number with floating point precision",
+            ValueType::Decimal => "A numeric type representing a decimal number exactly",
             ValueType::Empty => "Nothing",
             ValueType::Any => "Any type",
             ValueType::BinaryStream => "A stream of binary data",
@@ -207,6 +243,7 @@ impl ToString for ValueType {
             ValueType::Scope => "scope".to_string(),
             ValueType::Bool => "bool".to_string(),
             ValueType::Float => "float".to_string(),
+            ValueType::Decimal => "decimal".to_string(),
             ValueType::Empty => "empty".to_string(),
             ValueType::Any => "any".to_string(),
             ValueType::BinaryStream => "binary_stream".to_string(),
@@ -10,7 +10,7 @@ pub fn $name(mut context: ExecutionContext) -> CrushResult<()> {
     context.arguments.check_len(2)?;
     let l = context.arguments.value(0)?;
     let r = context.arguments.value(1)?;
-    match l.partial_cmp(&r) {
+    match l.compare(&r, &context.env, &context.printer)? {
         Some(ordering) => context.output.send(Value::Bool($op(ordering))),
         None => return argument_error(
             format!(
@@ -32,14 +32,14 @@ pub fn eq(mut context: ExecutionContext) -> CrushResult<()> {
     context.arguments.check_len(2)?;
     let l = context.arguments.value(0)?;
     let r = context.arguments.value(1)?;
-    context.output.send(Value::Bool(l.eq(&r)))
+    context.output.send(Value::Bool(l.equals(&r, &context.env, &context.printer)?))
 }
 
 pub fn neq(mut context: ExecutionContext) -> CrushResult<()> {
     context.arguments.check_len(2)?;
     let l = context.arguments.value(0)?;
     let r = context.arguments.value(1)?;
-    context.output.send(Value::Bool(!l.eq(&r)))
+    context.output.send(Value::Bool(!l.equals(&r, &context.env, &context.printer)?))
 }
 
 pub fn not(mut context: ExecutionContext) -> CrushResult<()> {
@@ -1,7 +1,7 @@
 use crate::lang::argument::Argument;
 use crate::lang::value::Value;
 use crate::lang::{table::TableReader, list::ListReader, r#struct::Struct, dict::DictReader, command::CrushCommand};
-use crate::lang::errors::{argument_error, CrushResult};
+use crate::lang::errors::{argument_error, CrushResult, mandate};
 use crate::lang::execution_context::{ExecutionContext, ArgumentVector};
 use crate::lang::stream::{empty_channel, Readable, black_hole};
 
@@ -54,10 +54,17 @@ pub fn r#for(mut context: ExecutionContext) -> CrushResult<()> {
 
     let body = context.arguments.command(1)?;
     let iter = context.arguments.remove(0);
-    let t = iter.value.value_type();
     let name = iter.argument_type.clone();
+    let value = match iter.value {
+        Value::Struct(s) if s.has_method("__iter__") =>
+            mandate(
+                s.call_method("__iter__", vec![], &context.env, &context.printer)?,
+                "Expected __iter__ to return a value")?,
+        v => v,
+    };
+    let t = value.value_type();
 
-    match (iter.argument_type.as_deref(), iter.value) {
+    match (iter.argument_type.as_deref(), value) {
         (_, Value::TableStream(o)) =>
             run(context, body, name, o),
         (_, Value::Table(r)) =>
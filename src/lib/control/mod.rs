@@ -1,14 +1,41 @@
 use crate::lang::scope::Scope;
-use crate::lang::errors::{CrushResult, argument_error, to_crush_error};
-use crate::lang::{value::Value, list::List, value::ValueType, execution_context::ExecutionContext, binary::BinaryReader};
+use crate::lang::errors::{CrushResult, argument_error, to_crush_error, mandate, error};
+use crate::lang::r#struct::Struct;
+use crate::lang::command_invocation::resolve_external_command;
+use chrono::Local;
+use crate::lang::{value::Value, list::List, value::ValueType, execution_context::ExecutionContext, binary::binary_channel};
+use crate::lang::printer::Printer;
+use crate::lang::command::CrushCommand;
+use crate::lang::dict::Dict;
+use crate::lang::stream::channels;
+use crate::lib::input::{columns, keyvalue};
+use crate::util::thread::build;
+use crate::util::file::{cwd, expand_tilde, feed};
+use crate::util::glob::Glob;
+use crate::util::regex::RegexFileMatcher;
 use std::env;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::process::{Child, ChildStderr, Command, ExitStatus, Stdio};
+use std::os::unix::process::ExitStatusExt;
+use std::thread::JoinHandle;
+use nix::sys::termios::{tcgetattr, tcsetattr, SetArg};
+use std::os::unix::io::AsRawFd;
 
 mod r#if;
 mod r#while;
 mod r#loop;
 mod r#for;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/**
+  Split a PATH style string into a list of directories.
+*/
+pub fn path_dirs(path: &str) -> Vec<Value> {
+    path.split(':')
+        .map(|s| Value::File(PathBuf::from(s)))
+        .collect()
+}
 
 pub fn r#break(context: ExecutionContext) -> CrushResult<()> {
     context.env.do_break()?;
@@ -20,62 +47,389 @@ pub fn r#continue(context: ExecutionContext) -> CrushResult<()> {
     Ok(())
 }
 
-pub fn cmd(mut context: ExecutionContext) -> CrushResult<()> {
+/**
+  Create the process described by the arguments. The first argument is the command to run.
+  The rest are its arguments, with named arguments turned into options.
+*/
+pub fn command(context: &mut ExecutionContext) -> CrushResult<Command> {
     if context.arguments.is_empty() {
         return argument_error("No command given");
     }
-    match context.arguments.remove(0).value {
-        Value::File(f) => {
-            let mut cmd = std::process::Command::new(f.as_os_str());
-            for a in context.arguments.drain(..) {
-                match a.argument_type {
-                    None => {
-                        cmd.arg(a.value.to_string());
+    let path = match context.arguments.remove(0).value {
+        Value::File(f) => expand_tilde(&f)?,
+        Value::String(s) => mandate(
+            resolve_external_command(&s, &context.env)?,
+            format!("Unknown command name {}", s).as_str())?,
+        _ => return argument_error("Not a valid command"),
+    };
+    let mut cmd = Command::new(path.as_os_str());
+    cmd.env_clear().envs(crate::lib::env::vars());
+    /* Children look up commands the same way we do. */
+    if let Some(Value::List(dirs)) = context.env.get("cmd_path")? {
+        cmd.env(
+            "PATH",
+            dirs.dump().iter().map(|d| d.to_string()).collect::<Vec<_>>().join(":"));
+    }
+    for a in context.arguments.drain(..) {
+        match a.argument_type {
+            None => {
+                cmd.args(expand_argument(a.value, &context.printer)?);
+            }
+            Some(name) => {
+                if name.len() == 1 {
+                    cmd.arg(format!("-{}", name));
+                } else {
+                    cmd.arg(format!("--{}", name));
+                }
+                match a.value {
+                    Value::Bool(true) => {}
+                    Value::File(f) => {
+                        cmd.arg(expand_tilde(&f)?);
                     }
-                    Some(name) => {
-                        if name.len() == 1 {
-                            cmd.arg(format!("-{}", name));
-                        } else {
-                            cmd.arg(format!("--{}", name));
-                        }
-                        match a.value {
-                            Value::Bool(true) => {}
-                            _ => {
-                                cmd.arg(a.value.to_string());
-                            }
-                        }
+                    _ => {
+                        cmd.arg(a.value.to_string());
                     }
                 }
             }
-            let output = to_crush_error(cmd.output())?;
-            let errors = String::from_utf8_lossy(&output.stderr);
-            for e in errors.split('\n') {
-                let err = e.trim();
+        }
+    }
+    Ok(cmd)
+}
+
+/**
+  Turn an unnamed argument into the arguments passed to an external command. Globs and
+  regexes are expanded to the matching files, or passed on unexpanded if nothing matches, like
+  other shells do. A leading ~/ or ~user is expanded to a home directory. A bare ~ is the
+  replace operator, so it can't be used for the home directory. Strings are passed on verbatim.
+*/
+fn expand_argument(value: Value, printer: &Printer) -> CrushResult<Vec<String>> {
+    let mut files = Vec::new();
+    match &value {
+        Value::File(f) => return Ok(vec![expand_tilde(f)?.to_string_lossy().to_string()]),
+        Value::Glob(g) => {
+            let pattern = g.to_string();
+            if pattern.starts_with('~') {
+                let (prefix, rest) = pattern.split_at(pattern.find('/').unwrap_or(pattern.len()));
+                let dir = expand_tilde(Path::new(prefix))?;
+                Glob::new(&format!("{}{}", dir.to_string_lossy(), rest))
+                    .glob_files(&cwd()?, &mut files)?;
+            } else {
+                g.glob_files(&cwd()?, &mut files)?;
+            }
+        }
+        Value::Regex(_, re) => {
+            let dir = cwd()?;
+            re.match_files(&dir, &mut files, printer);
+            files = files.into_iter()
+                .map(|f| f.strip_prefix(&dir).map(|f| f.to_path_buf()).unwrap_or(f))
+                .collect();
+        }
+        _ => return Ok(vec![value.to_string()]),
+    }
+    if files.is_empty() {
+        return Ok(vec![value.to_string()]);
+    }
+    files.sort();
+    Ok(files.iter().map(|f| f.to_string_lossy().to_string()).collect())
+}
+
+pub fn cmd(mut context: ExecutionContext) -> CrushResult<()> {
+    let cmd = command(&mut context)?;
+    spawn(cmd, context)
+}
+
+/**
+  Run the process, streaming its output, or if it is the last command of an interactive job,
+  handing it the terminal.
+*/
+pub fn spawn(cmd: Command, context: ExecutionContext) -> CrushResult<()> {
+    let input = context.input.recv().ok().filter(has_data);
+    if let Some(adapter) = adapter(&cmd, &context)? {
+        run_adapted(cmd, input, adapter, context)
+    } else if context.output.is_terminal() {
+        run_in_terminal(cmd, input, context)
+    } else {
+        run_piped(cmd, input, context)
+    }
+}
+
+pub fn run(mut context: ExecutionContext) -> CrushResult<()> {
+    let mut cmd = command(&mut context)?;
+    let input = context.input.recv().ok().filter(has_data);
+    cmd.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let start = Local::now();
+    let mut child = to_crush_error(cmd.spawn())?;
+    let stdin_handle = feed_input(&mut child, input, &context.printer)?;
+    let output = to_crush_error(child.wait_with_output())?;
+    if let Some(h) = stdin_handle {
+        let _ = h.join();
+    }
+    let status = match (output.status.code(), output.status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => -1,
+    };
+    context.output.send(
+        Value::Struct(
+            Struct::new(
+                vec![
+                    ("status".to_string(), Value::Integer(status as i128)),
+                    ("success".to_string(), Value::Bool(output.status.success())),
+                    ("stdout".to_string(), Value::string(&String::from_utf8_lossy(&output.stdout))),
+                    ("stderr".to_string(), Value::string(&String::from_utf8_lossy(&output.stderr))),
+                    ("duration".to_string(), Value::Duration(Local::now() - start)),
+                ],
+                None,
+            )
+        )
+    )
+}
+
+/**
+  Turn an unsuccessful exit of a child process into an error.
+*/
+fn check_status(status: ExitStatus) -> CrushResult<()> {
+    match (status.code(), status.signal()) {
+        (Some(0), _) => Ok(()),
+        (Some(code), _) => error(format!("Command exited with status {}", code).as_str()),
+        (None, Some(signal)) => error(format!("Command was killed by signal {}", signal).as_str()),
+        (None, None) => error("Command failed"),
+    }
+}
+
+/**
+  The output adapter registered in cmd_adapters for the program, if any.
+*/
+fn adapter(cmd: &Command, context: &ExecutionContext) -> CrushResult<Option<Box<dyn CrushCommand + Send + Sync>>> {
+    let name = match Path::new(cmd.get_program()).file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let args = cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect::<Vec<_>>();
+    if !has_header(name, &args) {
+        return Ok(None);
+    }
+    match context.env.get("cmd_adapters")? {
+        Some(Value::Dict(adapters)) => match adapters.get(&Value::string(name)) {
+            Some(Value::Command(adapter)) => Ok(Some(adapter)),
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/**
+  The short and long options that make a tool print a custom header or no header at all.
+*/
+fn header_options(program: &str) -> (&'static str, &'static [&'static str]) {
+    match program {
+        "ps" => ("oOh", &["--format", "--headers", "--no-headers", "--no-heading"]),
+        "lsblk" => ("nJP", &["--noheadings", "--json", "--pairs"]),
+        "lsof" => ("tF", &[]),
+        _ => ("", &[]),
+    }
+}
+
+/**
+  Whether the output of the tool is known to start with its usual header line, which the
+  columns adapter relies on. The first argument of ps may be a group of BSD style options
+  without a dash.
+*/
+fn has_header(program: &str, args: &[String]) -> bool {
+    let (short, long) = header_options(program);
+    !args.iter().enumerate().any(|(idx, a)| {
+        let options = if a.starts_with("--") {
+            return long.iter().any(|l| a == l || a.starts_with(&format!("{}=", l)));
+        } else if let Some(options) = a.strip_prefix('-') {
+            options
+        } else if program == "ps" && idx == 0 && a.chars().all(|c| c.is_ascii_alphabetic()) {
+            a.as_str()
+        } else {
+            return false;
+        };
+        options.chars().any(|c| short.contains(c))
+    })
+}
+
+/**
+  Run the command with its output piped through an output adapter.
+*/
+fn run_adapted(
+    cmd: Command,
+    input: Option<Value>,
+    adapter: Box<dyn CrushCommand + Send + Sync>,
+    context: ExecutionContext) -> CrushResult<()> {
+    let (sender, receiver) = channels();
+    let adapter_context = ExecutionContext {
+        input: receiver,
+        output: context.output.clone(),
+        arguments: vec![],
+        env: context.env.clone(),
+        this: None,
+        printer: context.printer.clone(),
+    };
+    let printer = context.printer.clone();
+    let adapter_handle = to_crush_error(
+        build("cmd:adapter").spawn(move || printer.handle_error(adapter.invoke(adapter_context))))?;
+    let res = run_piped(cmd, input, context.with_sender(sender));
+    let _ = adapter_handle.join();
+    res
+}
+
+/**
+  A stream without any columns can't carry any data. This is what the first command of a job
+  receives as input.
+*/
+fn has_data(input: &Value) -> bool {
+    match input {
+        Value::TableStream(s) => !s.types().is_empty(),
+        _ => true,
+    }
+}
+
+/**
+  Run the command with its output connected to a binary stream.
+*/
+fn run_piped(mut cmd: Command, input: Option<Value>, context: ExecutionContext) -> CrushResult<()> {
+    cmd.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = to_crush_error(cmd.spawn())?;
+    let stdin_handle = feed_input(&mut child, input, &context.printer)?;
+    let mut stdout = mandate(child.stdout.take(), "Missing standard output of child process")?;
+    let stderr = mandate(child.stderr.take(), "Missing standard error of child process")?;
+    let printer = context.printer.clone();
+    let stderr_handle = to_crush_error(
+        build("cmd:stderr").spawn(move || forward_errors(stderr, printer)))?;
+
+    let (mut output, input) = binary_channel();
+    let res = context.output.send(Value::BinaryStream(input))
+        .and_then(|_| to_crush_error(std::io::copy(&mut stdout, &mut output)));
+    drop(output);
+    if res.is_err() {
+        /* The consumer of our output has gone away, so there is no point in keeping the
+           child around. */
+        let _ = child.kill();
+    }
+    let status = to_crush_error(child.wait())?;
+    let _ = stderr_handle.join();
+    if let Some(h) = stdin_handle {
+        let _ = h.join();
+    }
+    match res {
+        Ok(_) => check_status(status),
+        Err(_) => Ok(()),
+    }
+}
+
+/**
+  Run the command with the terminal as its output. Unless the command has input, it also
+  reads directly from the terminal. This is what makes interactive programs like editors and
+  pagers work. The child shares the terminal with us, so it sees the real window size and
+  receives window size changes. Programs that crash or get killed often leave the terminal in
+  raw mode, so the terminal modes are restored once the child exits.
+*/
+fn run_in_terminal(mut cmd: Command, input: Option<Value>, context: ExecutionContext) -> CrushResult<()> {
+    let terminal = std::io::stdin().as_raw_fd();
+    let modes = tcgetattr(terminal).ok();
+    cmd.stdin(if input.is_some() { Stdio::piped() } else { Stdio::inherit() })
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    let res = to_crush_error(cmd.spawn())
+        .and_then(|mut child| {
+            let stdin_handle = feed_input(&mut child, input, &context.printer)?;
+            let status = to_crush_error(child.wait())?;
+            if let Some(h) = stdin_handle {
+                let _ = h.join();
+            }
+            check_status(status)
+        });
+    if let Some(modes) = modes {
+        let _ = tcsetattr(terminal, SetArg::TCSADRAIN, &modes);
+    }
+    res
+}
+
+/**
+  Start a thread feeding the input of the command to the child process.
+*/
+fn feed_input(child: &mut Child, input: Option<Value>, printer: &Printer) -> CrushResult<Option<JoinHandle<()>>> {
+    match (input, child.stdin.take()) {
+        (Some(input), Some(mut stdin)) => {
+            let printer = printer.clone();
+            Ok(Some(to_crush_error(
+                build("cmd:stdin").spawn(move || {
+                    match feed(input, &mut stdin) {
+                        Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+                        res => printer.handle_error(to_crush_error(res)),
+                    }
+                }))?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/**
+  Pass on everything the child process writes to standard error, one line at a time.
+*/
+fn forward_errors(stderr: ChildStderr, printer: Printer) {
+    for line in BufReader::new(stderr).lines() {
+        match line {
+            Ok(line) => {
+                let err = line.trim();
                 if !err.is_empty() {
-                    context.printer.error(err);
+                    printer.error(err);
                 }
             }
-            context.output.send(
-                Value::BinaryStream(
-                    BinaryReader::vec(&output.stdout)))
+            Err(_) => break,
         }
-        _ => argument_error("Not a valid command")
     }
 }
 
+fn adapter_command(
+    call: fn(ExecutionContext) -> CrushResult<()>,
+    name: &str,
+    signature: &'static str,
+    short_help: &'static str) -> Value {
+    Value::Command(<dyn CrushCommand>::command(
+        call, true,
+        vec!["global".to_string(), "input".to_string(), name.to_string()],
+        signature, short_help, None))
+}
+
+/**
+  Output adapters for common tools that don't need any configuration. They are not used
+  unless they are added to cmd_adapters, so that external commands print their usual
+  output by default.
+*/
+fn default_adapters() -> CrushResult<Dict> {
+    let adapters = Dict::new(ValueType::String, ValueType::Command);
+    let columns = adapter_command(
+        columns::columns, "columns",
+        "columns <column_name>=type:type... @files:(file|glob)",
+        "Parse whitespace separated columns of text with a header line");
+    let keyvalue = adapter_command(
+        keyvalue::keyvalue, "keyvalue",
+        "keyvalue [separator=separator:string] @files:(file|glob)",
+        "Parse lines of key/value pairs into a table");
+    for name in &["df", "ps", "lsblk", "lsof"] {
+        adapters.insert(Value::string(name), columns.clone())?;
+    }
+    adapters.insert(Value::string("lscpu"), keyvalue)?;
+    Ok(adapters)
+}
+
 pub fn declare(root: &Scope) -> CrushResult<()> {
     let e = root.create_lazy_namespace(
         "control",
         Box::new(move |env| {
             let path = List::new(ValueType::File, vec![]);
-            to_crush_error(env::var("PATH").map(|v| {
-                let mut dirs: Vec<Value> = v
-                    .split(':')
-                    .map(|s| Value::File(PathBuf::from(s)))
-                    .collect();
-                let _ = path.append(&mut dirs);
-            }))?;
+            to_crush_error(env::var("PATH").map(|v| path.append(&mut path_dirs(&v))))??;
             env.declare("cmd_path", Value::List(path))?;
+            env.declare("cmd_adapters", Value::Dict(Dict::new(ValueType::String, ValueType::Command)))?;
+            env.declare("cmd_default_adapters", Value::Dict(default_adapters()?))?;
 
             env.declare_condition_command(
                 "if",
@@ -143,9 +497,75 @@ pub fn declare(root: &Scope) -> CrushResult<()> {
                 "cmd", cmd, true,
                 "cmd external_command:(file|string) @arguments:any",
                 "Execute external commands",
-                None)?;
+                Some(r#"    Any input to the command is passed on to the standard input of the external
+    command. Binary data is passed on as is, strings are written verbatim, and
+    tables, lists and dicts are written as one line per row, with cells separated
+    by tabs.
+
+    Example:
+
+    ls | cmd /usr/bin/sort --r
+
+    Unquoted globs and regexes are expanded to the matching files, and a leading
+    ~ or ~user is expanded to a home directory. Quoted strings are passed on as is.
+
+    Example:
+
+    cmd rm %.tmp
+
+    If the external command exits with a non-zero status, an error is raised. Use
+    the run command to inspect the exit status instead.
+
+    If the name of the external command is a key in the cmd_adapters dict, the
+    output is piped through the corresponding command, which turns it into a
+    table. cmd_adapters is empty by default. The cmd_default_adapters dict holds
+    adapters for some common tools, e.g. df and ps, which are only used when the
+    options passed to the tool keep its usual header line.
+
+    Example:
+
+    cmd_adapters["uptime"] = {lines | select ^line}
+    cmd_adapters["df"] = cmd_default_adapters["df"]"#))?;
+            env.declare_command(
+                "run", run, true,
+                "run external_command:(file|string) @arguments:any",
+                "Execute an external command and return a struct describing the result",
+                Some(r#"    The struct contains the following fields:
+
+    * status, the exit status of the command
+    * success, true if the exit status is zero
+    * stdout, everything the command wrote to standard output
+    * stderr, everything the command wrote to standard error
+    * duration, how long the command took to run
+
+    Unlike the cmd command, a non-zero exit status is not an error.
+
+    Example:
+
+    if (run "git" "diff" --quiet):success {echo "clean"} {echo "dirty"}"#))?;
             Ok(())
         }))?;
     root.r#use(&e);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn header_detection() {
+        assert!(has_header("ps", &args(&["aux"])));
+        assert!(has_header("ps", &args(&["-ef"])));
+        assert!(!has_header("ps", &args(&["-o", "pid="])));
+        assert!(!has_header("ps", &args(&["axo", "pid"])));
+        assert!(!has_header("ps", &args(&["--no-headers"])));
+        assert!(has_header("lsblk", &args(&["-o", "NAME"])));
+        assert!(!has_header("lsblk", &args(&["-no", "NAME"])));
+        assert!(has_header("df", &args(&["-h"])));
+    }
+}
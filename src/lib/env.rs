@@ -0,0 +1,154 @@
+use crate::lang::scope::Scope;
+use crate::lang::errors::{CrushResult, argument_error, mandate};
+use crate::lang::execution_context::{ExecutionContext, ArgumentVector};
+use crate::lang::value::{Value, ValueType};
+use crate::lang::table::{ColumnType, Row};
+use crate::lib::control;
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /*
+      Changing the environment of the process isn't safe while other threads are running, so
+      crush keeps its own copy and hands it to the external commands it starts.
+    */
+    static ref VARS: Mutex<BTreeMap<OsString, OsString>> = Mutex::new(env::vars_os().collect());
+}
+
+/**
+  The environment variables that external commands are started with.
+*/
+pub fn vars() -> BTreeMap<OsString, OsString> {
+    VARS.lock().unwrap().clone()
+}
+
+/**
+  Keep cmd_path in sync with the PATH variable.
+*/
+fn update_cmd_path(context: &ExecutionContext, path: Option<&str>) -> CrushResult<()> {
+    if let Some(Value::List(dirs)) = context.env.get("cmd_path")? {
+        dirs.clear();
+        if let Some(path) = path {
+            dirs.append(&mut control::path_dirs(path))?;
+        }
+    }
+    Ok(())
+}
+
+fn get(mut context: ExecutionContext) -> CrushResult<()> {
+    context.arguments.check_len_range(1, 2)?;
+    let name = context.arguments.string(0)?;
+    let default = context.arguments.optional_string(1)?;
+    let value = VARS.lock().unwrap().get(&OsString::from(&name)).cloned();
+    match (value, default) {
+        (Some(value), _) => context.output.send(Value::string(&value.to_string_lossy())),
+        (None, Some(default)) => context.output.send(Value::string(&default)),
+        (None, None) => argument_error(format!("Unknown environment variable {}", name).as_str()),
+    }
+}
+
+fn set(context: ExecutionContext) -> CrushResult<()> {
+    let mut vars = VARS.lock().unwrap();
+    for arg in context.arguments.iter() {
+        let name = mandate(arg.argument_type.as_ref(), "Missing variable name")?;
+        let value = arg.value.to_string();
+        if name == "PATH" {
+            update_cmd_path(&context, Some(&value))?;
+        }
+        vars.insert(OsString::from(name), OsString::from(value));
+    }
+    Ok(())
+}
+
+fn unset(mut context: ExecutionContext) -> CrushResult<()> {
+    let mut vars = VARS.lock().unwrap();
+    for idx in 0..context.arguments.len() {
+        let name = context.arguments.string(idx)?;
+        if name == "PATH" {
+            update_cmd_path(&context, None)?;
+        }
+        vars.remove(&OsString::from(name));
+    }
+    Ok(())
+}
+
+fn list(context: ExecutionContext) -> CrushResult<()> {
+    context.arguments.check_len(0)?;
+    let output = context.output.initialize(vec![
+        ColumnType::new("name", ValueType::String),
+        ColumnType::new("value", ValueType::String),
+    ])?;
+    for (name, value) in vars() {
+        output.send(Row::new(vec![
+            Value::string(&name.to_string_lossy()),
+            Value::string(&value.to_string_lossy())]))?;
+    }
+    Ok(())
+}
+
+fn cmd(mut context: ExecutionContext) -> CrushResult<()> {
+    let mut vars = Vec::new();
+    let mut arguments = Vec::new();
+    for arg in context.arguments.drain(..) {
+        match arg.argument_type {
+            Some(name) => vars.push((name, arg.value.to_string())),
+            None => arguments.push(arg),
+        }
+    }
+    context.arguments = arguments;
+    let mut cmd = control::command(&mut context)?;
+    cmd.envs(vars);
+    control::spawn(cmd, context)
+}
+
+pub fn declare(root: &Scope) -> CrushResult<()> {
+    root.create_lazy_namespace(
+        "env",
+        Box::new(move |env| {
+            env.declare_command(
+                "get", get, false,
+                "env:get name:string [default:string]",
+                "Return the value of the specified environment variable",
+                Some(r#"    If the variable is not set, the default is returned. If no default is given,
+    this is an error.
+
+    Example:
+
+    env:get "EDITOR" "vi""#))?;
+            env.declare_command(
+                "set", set, false,
+                "env:set name=value...",
+                "Set environment variables",
+                Some(r#"    The variables are visible to all external commands started after this point.
+    Setting PATH also updates cmd_path.
+
+    Example:
+
+    env:set EDITOR="vim""#))?;
+            env.declare_command(
+                "unset", unset, false,
+                "env:unset name:string...",
+                "Remove environment variables",
+                None)?;
+            env.declare_command(
+                "list", list, false,
+                "env:list",
+                "Return a table containing all environment variables",
+                Some(r#"    The columns of the table are the name and the value of the variable."#))?;
+            env.declare_command(
+                "cmd", cmd, true,
+                "env:cmd name=value... external_command:(file|string) @arguments:any",
+                "Execute an external command with additional environment variables",
+                Some(r#"    All named arguments are set as environment variables of the external
+    command only. The unnamed arguments are the command to run and its arguments.
+
+    Example:
+
+    env:cmd LANG="C" "sort" "names.txt""#))?;
+            Ok(())
+        }))?;
+    Ok(())
+}
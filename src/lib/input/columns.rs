@@ -0,0 +1,159 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::{table::Row, value::Value, table::ColumnType, binary::BinaryReader};
+use crate::lang::errors::{CrushResult, to_crush_error, argument_error};
+use crate::lang::argument::ArgumentHandler;
+use crate::lang::value::ValueType;
+use crate::lang::ordered_string_map::OrderedStringMap;
+use std::io::{BufReader, BufRead};
+use std::path::PathBuf;
+use signature::signature;
+
+#[signature]
+#[derive(Debug)]
+struct Signature {
+    #[unnamed()]
+    files: Vec<PathBuf>,
+    #[named()]
+    columns: OrderedStringMap<ValueType>,
+}
+
+/**
+  Turn a header like "%CPU" or "Mounted on" into a name that can be used as a field.
+*/
+fn column_name(header: &str) -> String {
+    let name = header.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    let name = name.trim_matches('_').to_string();
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() => name,
+        _ => format!("_{}", name),
+    }
+}
+
+/**
+  Split a line into at most count whitespace separated fields. The last field contains the
+  remainder of the line, since e.g. the command column of ps often contains spaces.
+*/
+fn split(line: &str, count: usize) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        if res.len() == count - 1 {
+            res.push(rest.to_string());
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        res.push(rest[0..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    while res.len() < count {
+        res.push(String::new());
+    }
+    res
+}
+
+/**
+  The most specific type that the cell can be parsed as.
+*/
+fn infer_type(cell: &str) -> ValueType {
+    if ValueType::Integer.parse(cell).is_ok() {
+        ValueType::Integer
+    } else if cell.parse::<f64>().is_ok() {
+        ValueType::Float
+    } else {
+        ValueType::String
+    }
+}
+
+fn row(line: &str, names: &[String], types: &[ValueType]) -> CrushResult<Row> {
+    Ok(Row::new(
+        split(line, types.len()).iter()
+            .zip(names.iter().zip(types.iter()))
+            .map(|(cell, (name, t))| t.parse(cell).or_else(|_| argument_error(
+                format!(
+                    "Can't parse \"{}\" in column {} as {}, give the type of the column as an argument",
+                    cell, name, t.to_string()).as_str())))
+            .collect::<CrushResult<Vec<_>>>()?))
+}
+
+/**
+  The columns are taken from the header line and the first row, which is used to merge multi
+  word headers and to infer the column types. The remaining rows are parsed as they are read.
+*/
+pub fn columns(context: ExecutionContext) -> CrushResult<()> {
+    let cfg: Signature = Signature::parse(context.arguments, &context.printer)?;
+    let reader = BufReader::new(match cfg.files.len() {
+        0 => {
+            match context.input.recv()? {
+                Value::BinaryStream(b) => Ok(b),
+                Value::Binary(b) => Ok(<dyn BinaryReader>::vec(&b)),
+                _ => argument_error("Expected either a file to read or binary pipe input"),
+            }
+        }
+        _ => <dyn BinaryReader>::paths(cfg.files.clone()),
+    }?);
+
+    let mut lines = reader.lines()
+        .map(to_crush_error)
+        .filter(|l| !matches!(l, Ok(l) if l.trim().is_empty()));
+
+    let mut headers = match lines.next() {
+        Some(header) => header?.split_whitespace().map(|s| s.to_string()).collect::<Vec<_>>(),
+        None => return argument_error("Missing header line"),
+    };
+    let first = lines.next().transpose()?;
+
+    /* Headers like "Mounted on" span several words. If the first row has fewer fields than
+       there are words in the header, the trailing words are merged into one column. */
+    if let Some(first) = &first {
+        let count = first.split_whitespace().count();
+        if count > 0 && count < headers.len() {
+            let last = headers.split_off(count - 1).join(" ");
+            headers.push(last);
+        }
+    }
+
+    let cells = first.as_ref().map(|l| split(l, headers.len()));
+    let names = headers.iter().map(|h| column_name(h)).collect::<Vec<_>>();
+    let types = names.iter()
+        .enumerate()
+        .map(|(idx, name)| cfg.columns.iter()
+            .find(|(column, _)| column == name)
+            .map(|(_, t)| t.clone())
+            .unwrap_or_else(|| cells.as_ref().map(|c| infer_type(&c[idx])).unwrap_or(ValueType::String)))
+        .collect::<Vec<_>>();
+
+    let output = context.output.initialize(
+        names.iter()
+            .zip(types.iter())
+            .map(|(n, t)| ColumnType::new(n, t.clone()))
+            .collect())?;
+
+    if let Some(first) = first {
+        output.send(row(&first, &names, &types)?)?;
+    }
+    for line in lines {
+        output.send(row(&line?, &names, &types)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_names() {
+        assert_eq!(column_name("%CPU"), "cpu");
+        assert_eq!(column_name("Mounted on"), "mounted_on");
+        assert_eq!(column_name("1K-blocks"), "_1k_blocks");
+    }
+
+    #[test]
+    fn split_keeps_remainder_in_last_field() {
+        assert_eq!(split("root  1 /sbin/init splash", 3), vec!["root", "1", "/sbin/init splash"]);
+        assert_eq!(split("a", 2), vec!["a", ""]);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::{table::Row, value::Value, table::ColumnType, binary::BinaryReader};
+use crate::lang::errors::{CrushResult, to_crush_error, argument_error};
+use crate::lang::argument::ArgumentHandler;
+use crate::lang::value::ValueType;
+use std::io::{BufReader, BufRead};
+use std::path::PathBuf;
+use signature::signature;
+
+#[signature]
+#[derive(Debug)]
+struct Signature {
+    #[unnamed()]
+    files: Vec<PathBuf>,
+    separator: Option<char>,
+}
+
+pub fn keyvalue(context: ExecutionContext) -> CrushResult<()> {
+    let cfg: Signature = Signature::parse(context.arguments, &context.printer)?;
+    let separator = cfg.separator.unwrap_or(':');
+    let output = context.output.initialize(vec![
+        ColumnType::new("key", ValueType::String),
+        ColumnType::new("value", ValueType::String),
+    ])?;
+
+    let reader = BufReader::new(match cfg.files.len() {
+        0 => {
+            match context.input.recv()? {
+                Value::BinaryStream(b) => Ok(b),
+                Value::Binary(b) => Ok(<dyn BinaryReader>::vec(&b)),
+                _ => argument_error("Expected either a file to read or binary pipe input"),
+            }
+        }
+        _ => <dyn BinaryReader>::paths(cfg.files),
+    }?);
+
+    for line in reader.lines() {
+        let line = to_crush_error(line)?;
+        let mut split = line.splitn(2, separator);
+        if let (Some(key), Some(value)) = (split.next(), split.next()) {
+            output.send(Row::new(vec![
+                Value::string(key.trim()),
+                Value::string(value.trim()),
+            ]))?;
+        }
+    }
+    Ok(())
+}
@@ -11,9 +11,8 @@ use crate::{
 };
 use crate::lang::errors::{CrushResult, to_crush_error};
 use crate::lang::binary::BinaryReader;
-use crate::lang::printer::Printer;
 
-fn run(input: Box<dyn BinaryReader>, output: OutputStream, printer: &Printer) -> CrushResult<()> {
+fn run(input: Box<dyn BinaryReader>, output: OutputStream) -> CrushResult<()> {
     let mut reader = BufReader::new(input);
     let mut line = String::new();
     loop {
@@ -22,7 +21,7 @@ fn run(input: Box<dyn BinaryReader>, output: OutputStream, printer: &Printer) ->
             break;
         }
         let s = if line.ends_with('\n') {&line[0..line.len()-1]} else {&line[..]};
-        printer.handle_error(output.send(Row::new(vec![Value::string(s)])));
+        output.send(Row::new(vec![Value::string(s)]))?;
         line.clear();
     }
     Ok(())
@@ -31,5 +30,5 @@ fn run(input: Box<dyn BinaryReader>, output: OutputStream, printer: &Printer) ->
 
 pub fn perform(mut context: ExecutionContext) -> CrushResult<()> {
     let output = context.output.initialize(vec![ColumnType::new("line", ValueType::String)])?;
-    run(context.reader()?, output, &context.printer)
+    run(context.reader()?, output)
 }
@@ -8,6 +8,9 @@ use crate::lang::pretty_printer::PrettyPrinter;
 mod lines;
 mod csv;
 mod http;
+pub mod columns;
+pub mod keyvalue;
+mod text;
 
 pub fn val(mut context: ExecutionContext) -> CrushResult<()> {
     context.arguments.check_len(1)?;
@@ -19,7 +22,7 @@ pub fn dir(mut context: ExecutionContext) -> CrushResult<()> {
     context.output.send(
         Value::List(List::new(
             ValueType::String,
-            context.arguments.value(0)?.fields()
+            context.arguments.value(0)?.fields(&context.env)?
                 .drain(..)
                 .map(|n| Value::String(n))
                 .collect()))
@@ -28,7 +31,7 @@ pub fn dir(mut context: ExecutionContext) -> CrushResult<()> {
 
 fn echo(mut context: ExecutionContext) -> CrushResult<()> {
     for arg in context.arguments.drain(..) {
-        PrettyPrinter::new(context.printer.clone()).print_value(arg.value);
+        PrettyPrinter::new(context.printer.clone(), context.env.clone()).print_value(arg.value);
     }
     Ok(())
 }
@@ -63,9 +66,45 @@ pub fn declare(root: &Scope) -> CrushResult<()> {
 
     csv separator="," head=1 name=string age=integer nick=string"#))?;
 
+            env.declare_command(
+                "columns", columns::columns, true,
+                "columns <column_name>=type:type... @files:(file|glob)",
+                "Parse whitespace separated columns of text with a header line",
+                Some(r#"    This is the format used by many command line tools, like ps, df and lsblk.
+    Column names are taken from the header line, lowercased and with
+    non-alphanumeric characters replaced by underscores, so e.g. %CPU becomes
+    cpu. The type of each column is inferred from the first row, unless specified
+    using a named argument. The last column contains the rest of each line.
+    Rows are parsed as they are read.
+
+    Example:
+
+    cmd "ps" "aux" | columns pid=integer | where {cpu > 10.0}"#))?;
+            env.declare_command(
+                "keyvalue", keyvalue::keyvalue, true,
+                "keyvalue [separator=separator:string] @files:(file|glob)",
+                "Parse lines of key/value pairs into a table",
+                Some(r#"    Each line is split on the first occurrence of the separator, which defaults
+    to a colon. Lines without a separator are ignored.
+
+    Example:
+
+    cat /proc/meminfo | keyvalue"#))?;
             env.declare_command(
                 "echo", echo, false,
                 "echo @value:any", "Prints all arguments directly to the screen", None)?;
+            env.declare_command(
+                "text", text::text, true,
+                "text [value:any] [--trim]",
+                "Convert a value to a string",
+                Some(r#"    The value can either be specified as an argument or it can be read from a pipe.
+    Binary data is decoded as UTF-8, other values are laid out the same way they
+    would be printed to the screen. Trailing newlines are removed. If --trim is
+    given, all leading and trailing whitespace is removed.
+
+    Example:
+
+    x := (cmd "git" "rev-parse" "HEAD" | text)"#))?;
             env.declare_command(
                 "val", val, false,
                 "val value:any",
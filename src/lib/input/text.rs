@@ -0,0 +1,49 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, argument_error, to_crush_error, error};
+use crate::lang::value::Value;
+use crate::lang::pretty_printer::PrettyPrinter;
+use std::io::Read;
+
+/**
+  Render a value as text. Binary data must be valid UTF-8, everything else is laid out the
+  same way the pretty printer would, except that single column tables get one row per line.
+*/
+fn render(value: Value, context: &ExecutionContext) -> CrushResult<String> {
+    let bytes = match value {
+        Value::String(s) => return Ok(s.to_string()),
+        Value::Binary(b) => b,
+        Value::BinaryStream(mut b) => {
+            let mut buff = Vec::new();
+            to_crush_error(b.read_to_end(&mut buff))?;
+            buff
+        }
+        value => {
+            let pp = PrettyPrinter::capture(context.printer.clone(), context.env.clone());
+            pp.print_value(value);
+            return Ok(pp.into_text());
+        }
+    };
+    match String::from_utf8(bytes) {
+        Ok(s) => Ok(s),
+        Err(_) => error("Binary data is not valid UTF-8"),
+    }
+}
+
+pub fn text(mut context: ExecutionContext) -> CrushResult<()> {
+    let mut trim = false;
+    let mut value = None;
+    for arg in context.arguments.drain(..) {
+        match (arg.argument_type.as_deref(), arg.value) {
+            (Some("trim"), Value::Bool(b)) => trim = b,
+            (None, v) if value.is_none() => value = Some(v),
+            _ => return argument_error("Expected at most one value and an optional trim flag"),
+        }
+    }
+    let value = match value {
+        Some(v) => v,
+        None => context.input.recv()?,
+    };
+    let text = render(value, &context)?;
+    context.output.send(Value::string(
+        if trim { text.trim() } else { text.trim_end_matches('\n') }))
+}
@@ -17,6 +17,7 @@ mod math;
 mod toml;
 mod json;
 mod user;
+mod env;
 
 use crate::{lang::scope::Scope, lang::errors::CrushResult};
 use crate::lang::execute;
@@ -60,6 +61,11 @@ fn load_external_namespace(name: &str, file: &Path, root: &Scope, printer: &Prin
         for (k,v) in data.mapping {
             env.declare(&k, v)?;
         }
+        for (t, mut methods) in data.extensions {
+            for (k, v) in methods.drain() {
+                env.extend(&t, &k, v)?;
+            }
+        }
         Ok(())
     }))
 }
@@ -79,6 +85,7 @@ pub fn declare(root: &Scope, printer: &Printer, output: &ValueSender) -> CrushRe
     toml::declare(root)?;
     json::declare(root)?;
     user::declare(root)?;
+    env::declare(root)?;
 
     declare_external(root, printer, output)?;
     root.readonly();
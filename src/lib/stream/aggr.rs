@@ -1,15 +1,18 @@
-use crate::lang::{Closure, Argument, ExecutionContext, Value, ColumnType, RowsReader, Row, JobJoinHandle};
-use crate::lang::stream::{Readable, ValueSender};
-use crate::lang::errors::{CrushResult, argument_error, mandate, error};
-use crate::lib::command_util::{find_field, find_field_from_str};
-use crate::lang::printer::Printer;
-use crossbeam::{Receiver, bounded, unbounded, Sender};
-use crate::util::thread::{handle, build};
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, argument_error, error, mandate, to_crush_error};
+use crate::lang::{argument::Argument, table::ColumnType, table::Row, table::ColumnVec};
+use crate::lang::value::{Value, ValueType};
+use crate::lang::command::CrushCommand;
+use crate::lang::stream::{channels, streams, empty_channel, black_hole, OutputStream, InputStream, ValueSender};
+use crate::util::thread::build;
+use crossbeam::{unbounded, Receiver, Sender};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::thread::JoinHandle;
 
-struct Aggregation {
-    idx: usize,
-    name: String,
-    command: Closure,
+pub struct Aggregation {
+    pub name: String,
+    pub command: Box<dyn CrushCommand + Send + Sync>,
 }
 
 pub struct Config {
@@ -17,300 +20,320 @@ pub struct Config {
     aggregations: Vec<Aggregation>,
 }
 
-pub fn parse(input_type: &[ColumnType], argument: Vec<Argument>) -> CrushResult<Config> {
-    let mut table=None;
+/**
+  The only column of the input that contains tables, if there is exactly one.
+*/
+fn guess_table(input_type: &[ColumnType]) -> CrushResult<usize> {
+    let tables = input_type.iter()
+        .enumerate()
+        .filter(|(_, t)| matches!(t.cell_type, ValueType::TableStream(_) | ValueType::Table(_)))
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    match tables.len() {
+        1 => Ok(tables[0]),
+        n => argument_error(
+            format!("Could not guess table to aggregate, expected one table column, found {}", n).as_str()),
+    }
+}
+
+pub fn parse(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<Config> {
+    let mut table_idx = None;
     let mut aggregations = Vec::new();
-    let mut next_idx = input_type.len();
 
-    for a in &argument {
-        match (a.name.as_deref(), a.value) {
-            (Some("column"), Value::Field(name)) => {
-                table = Some(find_field(name.as_ref(), input_type)?);
+    for a in arguments {
+        match (a.argument_type, a.value) {
+            (Some(name), Value::Field(field)) if name == "column" => {
+                table_idx = Some(input_type.find(&field)?);
             }
-            (Some(name), Value::Closure(command)) => {
-                aggregations.push(
-                    Aggregation {
-                        command,
-                        name: Box::from(name),
-                        idx: find_field_from_str(name, input_type)
-                            .unwrap_or_else(|| {next_idx += 1; next_idx - 1})
-                    }
-                )
+            (Some(name), Value::Command(command)) => {
+                aggregations.push(Aggregation { name, command });
             }
-            _ => return argument_error("Bad argument"),
+            _ => return argument_error("Expected named closures to aggregate with"),
         }
     }
-
-    Ok(Config {
-        table_idx: mandate(table, "Missing table spec")?,
-        aggregations,
-    })
-/*
-    if argument.len() < 2 {
-        return Err(argument_error("Expected at least two paramaters"));
+    if aggregations.is_empty() {
+        return argument_error("No aggregations specified");
     }
-    let (table_idx, aggregations) = match (argument.len() % 2, argument[0].name.is_none(), &argument[0].value) {
-        (0, false, _) => (guess_table(input_type)?, &argument[..]),
-        (1, true, Value::Field(f)) => (find_field(&f, input_type)?, &argument[1..]),
-        _ => return Err(argument_error("Could not find table to aggregate")),
-    };
 
-    match &input_type[table_idx].cell_type {
-        ValueType::Rows(sub_type) |
-        ValueType::Output(sub_type) => {
-            let output_definition = aggregations
-                .chunks(2)
-                .into_iter()
-                .map(|args| {
-                    let spec = &args[0];
-                    let clos = &args[1];
-                    match (&spec.name, &spec.value, &clos.value) {
-                        (Some(name), Value::Field(f), Value::Closure(c)) =>
-                            Ok((
-                                name.to_string(),
-                                find_field(&f, sub_type)?,
-                                c.clone()
-                            )),
-                        _ => Err(error("Invalid aggragation spec")),
-                    }
-                })
-                .collect::<JobResult<Vec<(String, usize, Closure)>>>()?;
-            Ok(Config {
-                table_idx,
-                output_definition,
-            })
-        }
-        _ => {
-            Err(argument_error("No table to aggregate on found"))
-        }
+    let table_idx = match table_idx {
+        Some(idx) => idx,
+        None => guess_table(input_type)?,
+    };
+    match input_type[table_idx].cell_type {
+        ValueType::TableStream(_) | ValueType::Table(_) => Ok(Config { table_idx, aggregations }),
+        _ => argument_error("The aggregated column must contain tables"),
     }
-    */
 }
 
-/*
-
-pub fn guess_table(input_type: &[ColumnType]) -> JobResult<usize> {
-    let tables: Vec<usize> = input_type
-        .iter()
-        .enumerate()
-        .flat_map(|(idx, t)| {
-            match &t.cell_type {
-                ValueType::Output(_) | ValueType::Rows(_) => Some(idx),
-                _ => None,
-            }
-        }).collect();
-    if tables.len() == 1 {
-        Ok(tables[0])
-    } else {
-        Err(argument_error(format!("Could not guess tables to join, expected one table, found {}", tables.len()).as_str()))
+/**
+  Call the command with the value as its input and return its materialized output. The output is
+  read while the command runs, since a command that writes more rows than fit in a stream would
+  otherwise block forever.
+*/
+pub fn call(command: &(dyn CrushCommand + Send + Sync), input: Value, context: ExecutionContext) -> CrushResult<Option<Value>> {
+    let (input_sender, input_receiver) = channels();
+    input_sender.send(input)?;
+    let (sender, receiver) = channels();
+    let collector = to_crush_error(build("collector").spawn(
+        move || receiver.recv().ok().map(Value::materialize)))?;
+    let res = command.invoke(ExecutionContext {
+        input: input_receiver,
+        output: sender,
+        ..context
+    });
+    match collector.join() {
+        Ok(value) => res.map(|_| value),
+        Err(_) => error("Failed to read the output of the command"),
     }
 }
-*/
 
-fn create_writer(
-    uninitialized_output: ValueSender,
-    mut output_names: Vec<Option<String>>,
-    writer_input: Receiver<Row>) ->
-    JobJoinHandle {
-    handle(build("aggr-writer".to_string()).spawn(
-        move || {
-            let output = match writer_input.recv() {
-                Ok(row) => {
-                    let tmp = uninitialized_output.initialize(
-                        row.cells
-                            .iter()
-                            .enumerate()
-                            .map(|(idx, cell)| ColumnType { name: output_names[idx].take(), cell_type: cell.value_type() })
-                            .collect()
-                    )?;
-                    tmp.send(row);
-                    tmp
-                }
-                Err(_) => return Err(error("No output")),
-            };
+pub type Consumer = Box<dyn FnOnce(InputStream) -> CrushResult<Value> + Send>;
 
-            loop {
-                match writer_input.recv() {
-                    Ok(row) => {
-                        output.send(row);
-                    }
-                    Err(_) => break,
-                }
-            }
-            Ok(())
-        }))
+/**
+  Feeds a copy of every row to a number of consumers, each running in a thread of its own. The
+  streams to the consumers are bounded, so rows are sent no faster than the slowest consumer
+  reads them. Consumers that stop reading, e.g. head, are left out.
+*/
+pub struct Fanout {
+    outputs: Vec<Option<OutputStream>>,
+    handles: Vec<(String, JoinHandle<CrushResult<Value>>)>,
 }
 
-pub fn create_collector(
-    rest_input: InputStream,
-    uninitialized_inputs: Vec<ValueReceiver>,
-    writer_output: Sender<Row>) -> JobJoinHandle {
-    handle(build("aggr-collector".to_string()).spawn(
-        move || {
-            match rest_input.recv() {
-                Ok(mut partial_row) => {
-                    for ui in uninitialized_inputs {
-                        let i = ui.initialize_stream()?;
-                        match i.recv() {
-                            Ok(mut r) => {
-                                partial_row.cells.push(std::mem::replace(&mut r.cells[0], Value::Integer(0)));
-                            }
-                            Err(_) => return Err(error("Missing value")),
-                        }
-                    }
-                    writer_output.send(partial_row);
+impl Fanout {
+    pub fn start(types: &[ColumnType], consumers: Vec<(String, Consumer)>) -> CrushResult<Fanout> {
+        let mut outputs = Vec::new();
+        let mut handles = Vec::new();
+        for (name, consumer) in consumers {
+            let (output, input) = streams(types.to_vec());
+            handles.push((name.clone(), to_crush_error(build(&name).spawn(move || consumer(input)))?));
+            outputs.push(Some(output));
+        }
+        Ok(Fanout { outputs, handles })
+    }
+
+    /**
+      Send the row to all consumers. Returns false once no consumer is reading anymore.
+    */
+    pub fn send(&mut self, row: &Row) -> bool {
+        for output in self.outputs.iter_mut() {
+            if let Some(o) = output {
+                if o.send(row.clone()).is_err() {
+                    *output = None;
                 }
-                Err(_) => {}
             }
-            Ok(())
-        }))
-}
-
-pub fn pump_table(
-    job_output: &mut impl Readable,
-    outputs: Vec<OutputStream>,
-    output_definition: &Vec<(String, usize, Closure)>) -> JobResult<()> {
-    let stream_to_column_mapping = output_definition.iter().map(|(_, off, _)| *off).collect::<Vec<usize>>();
+        }
+        self.outputs.iter().any(Option::is_some)
+    }
 
-    loop {
-        match job_output.read() {
-            Ok(mut inner_row) => {
-                for stream_idx in 0..stream_to_column_mapping.len() {
-                    outputs[stream_idx].send(Row { cells: vec![inner_row.cells.replace(stream_to_column_mapping[stream_idx], Value::Integer(0))] })?;
-                }
+    /**
+      Close the streams and wait for the results of all consumers.
+    */
+    pub fn finish(self) -> CrushResult<Vec<(String, Value)>> {
+        drop(self.outputs);
+        let mut res = Vec::new();
+        for (name, handle) in self.handles {
+            match handle.join() {
+                Ok(value) => res.push((name, value?)),
+                Err(_) => return error(format!("{} failed", name).as_str()),
             }
-            Err(_) => break,
         }
+        Ok(res)
     }
-    Ok(())
 }
 
-fn create_aggregator(
-    name: &str,
-    idx: usize,
-    c: &Closure,
-    input_type: &[ColumnType],
-    uninitialized_inputs: &mut Vec<ValueReceiver>,
-    outputs: &mut Vec<OutputStream>,
-    env: &Env,
-    printer: &Printer) -> JobResult<JobJoinHandle> {
-    let (first_output, first_input) = streams(vec![
-        ColumnType::named(name, input_type[idx].value_type.clone())
-    ]);
-    let (last_output, last_input) = streams();
-    outputs.push(first_output);
-    uninitialized_inputs.push(last_input);
-
-    let local_printer = printer.clone();
-    let local_env = env.clone();
-    let cc = c.clone();
-    Ok(handle(build("aggr-aggregator".to_string()).spawn(
-        move || {
-            cc.spawn_and_execute(CompileContext {
-                input: first_input,
-                output: last_output,
-                arguments: vec![],
-                env: local_env,
-                printer: local_printer,
-            });
-            Ok(())
-        })))
+fn consumer(aggregation: &Aggregation, context: &ExecutionContext) -> Consumer {
+    let command = aggregation.command.clone();
+    let name = aggregation.name.clone();
+    let context = context.clone();
+    Box::new(move |input| mandate(
+        call(command.as_ref(), Value::TableStream(input), context)?,
+        format!("Aggregation {} did not return a value", name).as_str()))
 }
 
-fn handle_row(
-    row: Row,
-    config: &Config,
-    job_output: &mut impl Readable,
-    printer: &Printer,
-    env: &Env,
-    input: &InputStream,
-    writer_output: &Sender<Row>) -> JobResult<()> {
-    let mut outputs: Vec<OutputStream> = Vec::new();
-    let mut uninitialized_inputs: Vec<ValueReceiver> = Vec::new();
-    let mut aggregator_handles: Vec<JobJoinHandle> = Vec::new();
-
-    let (uninit_rest_output, uninit_rest_input) = streams();
-    let mut rest_output_type = input.get_type().clone();
-    rest_output_type.remove(config.table_idx);
-    let rest_output = uninit_rest_output.initialize(rest_output_type)?;
-    let rest_input = uninit_rest_input.initialize()?;
+/**
+  Start every aggregation, so that rows can be fed to them as they arrive.
+*/
+pub fn start_aggregations(
+    types: &[ColumnType],
+    aggregations: &[Aggregation],
+    context: &ExecutionContext) -> CrushResult<Fanout> {
+    Fanout::start(
+        types,
+        aggregations.iter().map(|a| (a.name.clone(), consumer(a, context))).collect())
+}
 
-    for (name, idx, c) in config.output_definition.iter() {
-        aggregator_handles.push(create_aggregator(
-            name.as_str(),
-            *idx,
-            c,
-            job_output.get_type(),
-            &mut uninitialized_inputs,
-            &mut outputs,
-            env,
-            printer)?);
+/**
+  Run every aggregation with the table as its input and return the results.
+*/
+pub fn run_aggregations(
+    table: Value,
+    aggregations: &[Aggregation],
+    context: &ExecutionContext) -> CrushResult<Vec<Value>> {
+    let mut table = mandate(table.readable(), "Expected a table")?;
+    let mut fanout = start_aggregations(table.types(), aggregations, context)?;
+    while let Ok(row) = table.read() {
+        if !fanout.send(&row) {
+            break;
+        }
     }
+    Ok(fanout.finish()?.into_iter().map(|(_, v)| v).collect())
+}
 
-    let collector_handle = create_collector(
-        rest_input,
-        uninitialized_inputs,
-        writer_output.clone());
-
-    rest_output.send(row)?;
-    drop(rest_output);
+/**
+  The number of rows that are aggregated at the same time.
+*/
+const WORKERS: usize = 8;
 
-    pump_table(job_output, outputs, &config.output_definition)?;
+/**
+  Items waiting to be processed by the workers. Every item is numbered, so that the results can
+  be put back in order.
+*/
+pub struct Jobs<T> {
+    sender: Sender<(usize, T)>,
+    count: usize,
+}
 
-    for h in aggregator_handles {
-        h.join(printer);
+impl<T> Jobs<T> {
+    pub fn send(&mut self, item: T) -> CrushResult<()> {
+        if self.sender.send((self.count, item)).is_err() {
+            return error("The workers have stopped");
+        }
+        self.count += 1;
+        Ok(())
     }
-    collector_handle.join(printer);
-    Ok(())
 }
 
-pub fn run(config: Config, printer: &Printer, env: &Env, mut input: impl Readable, uninitialized_output: ValueSender) -> JobResult<()> {
-    let (writer_output, writer_input) = bounded::<Row>(16);
+/**
+  The results of the workers, in the order the items were sent in.
+*/
+pub struct Results<R> {
+    receiver: Receiver<(usize, R)>,
+    pending: BTreeMap<usize, R>,
+    next: usize,
+}
+
+impl<R> Iterator for Results<R> {
+    type Item = R;
 
-    let mut output_names = input.get_type().iter().map(|t| t.name.clone()).collect::<Vec<Option<String>>>();
-    output_names.remove(config.table_idx);
-    for (name, _, _) in &config.output_definition {
-        output_names.push(Some(name.clone()));
+    fn next(&mut self) -> Option<R> {
+        loop {
+            if let Some(res) = self.pending.remove(&self.next) {
+                self.next += 1;
+                return Some(res);
+            }
+            let (idx, res) = self.receiver.recv().ok()?;
+            self.pending.insert(idx, res);
+        }
     }
+}
 
-    let writer_handle = create_writer(uninitialized_output, output_names, writer_input);
-
-    loop {
-        match input.recv() {
-            Ok(mut row) => {
-                let table_cell = row.cells.remove(config.table_idx);
-                match table_cell {
-                    Value::Output(mut job_output) =>
-                        handle_row(row, &config, &mut job_output.stream, printer, env, &input, &writer_output)?,
-                    Value::Rows(mut rows) =>
-                        handle_row(row, &config, &mut RowsReader::new(rows), printer, env, &input, &writer_output)?,
-                    _ => {
-                        printer.job_error(error("Wrong column type"));
-                        break;
-                    }
+/**
+  Start a fixed number of threads that call the function on every item sent to the jobs. Items
+  are queued rather than refused while all workers are busy, since the tables in a row, e.g.
+  from group, are often only complete once all of the input has been read.
+*/
+pub fn workers<T: Send + 'static, R: Send + 'static>(
+    name: &str,
+    function: impl Fn(T) -> R + Clone + Send + 'static) -> CrushResult<(Jobs<T>, Results<R>)> {
+    let (job_sender, job_receiver) = unbounded::<(usize, T)>();
+    let (result_sender, result_receiver) = unbounded();
+    for _ in 0..WORKERS {
+        let jobs = job_receiver.clone();
+        let results = result_sender.clone();
+        let function = function.clone();
+        to_crush_error(build(name).spawn(move || {
+            for (idx, item) in jobs.iter() {
+                if results.send((idx, function(item))).is_err() {
+                    break;
                 }
             }
-            Err(_) => { break; }
-        }
+        }))?;
     }
-    drop(writer_output);
-    writer_handle.join(printer);
-    Ok(())
+    Ok((
+        Jobs { sender: job_sender, count: 0 },
+        Results { receiver: result_receiver, pending: BTreeMap::new(), next: 0 }))
 }
 
-fn perform_on(arguments: Vec<Argument>, input: &Readable, sender: ValueSender) -> CrushResult<()> {
-    let config = parse(input.types(), arguments)?;
+/**
+  Replace the table of the row with the results of the aggregations.
+*/
+fn aggregate(mut row: Vec<Value>, config: &Config, context: &ExecutionContext) -> CrushResult<Row> {
+    let table = row.remove(config.table_idx);
+    row.append(&mut run_aggregations(table, &config.aggregations, context)?);
+    Ok(Row::new(row))
+}
+
+fn output_type(input_type: &[ColumnType], config: &Config, first: Option<&Row>) -> Vec<ColumnType> {
+    let mut res = input_type.to_vec();
+    res.remove(config.table_idx);
+    let first_aggregation = res.len();
+    for (idx, aggregation) in config.aggregations.iter().enumerate() {
+        let cell_type = match first {
+            Some(row) => row.cells()[first_aggregation + idx].value_type(),
+            None => ValueType::Any,
+        };
+        res.push(ColumnType::new(&aggregation.name, cell_type));
+    }
+    res
+}
+
+/**
+  Write the results in the order of the input. The output types are those of the first result.
+*/
+fn write(
+    config: &Config,
+    input_type: &[ColumnType],
+    results: Results<CrushResult<Row>>,
+    output: ValueSender,
+) -> CrushResult<()> {
+    let mut stream: Option<OutputStream> = None;
+    for res in results {
+        let res = res?;
+        if stream.is_none() {
+            stream = Some(output.initialize(output_type(input_type, config, Some(&res)))?);
+        }
+        if let Some(s) = &stream {
+            s.send(res)?;
+        }
+    }
+    if stream.is_none() {
+        output.initialize(output_type(input_type, config, None))?;
+    }
     Ok(())
 }
 
 pub fn perform(context: ExecutionContext) -> CrushResult<()> {
-    match context.input.recv()? {
-        Value::Stream(s) => {
-            perform_on(context.arguments, &s.stream, context.output)
-        }
-        Value::Rows(r) => {
-            perform_on(context.arguments, &r.reader(), context.output)
+    match context.input.recv()?.readable() {
+        Some(mut input) => {
+            let input_type = input.types().to_vec();
+            let config = Arc::new(parse(&input_type, context.arguments)?);
+            let base_context = ExecutionContext {
+                input: empty_channel(),
+                output: black_hole(),
+                arguments: vec![],
+                env: context.env,
+                this: None,
+                printer: context.printer,
+            };
+
+            let aggregate_config = config.clone();
+            let (mut jobs, results) = workers(
+                "aggr",
+                move |row: Row| aggregate(row.into_vec(), &aggregate_config, &base_context))?;
+            let output = context.output;
+            let writer = to_crush_error(build("aggr-writer").spawn(
+                move || write(&config, &input_type, results, output)))?;
+            while let Ok(row) = input.read() {
+                if jobs.send(row).is_err() {
+                    break;
+                }
+            }
+            drop(jobs);
+            match writer.join() {
+                Ok(res) => res,
+                Err(_) => error("Aggregation failed"),
+            }
         }
-        _ => argument_error("Expected a struct"),
+        None => error("Expected a stream"),
     }
 }
@@ -16,6 +16,11 @@ pub fn perform(context: ExecutionContext) -> CrushResult<()> {
         Value::Table(r) => context.output.send(Value::Integer(r.rows().len() as i128)),
         Value::List(r) => context.output.send(Value::Integer(r.len() as i128)),
         Value::Dict(r) => context.output.send(Value::Integer(r.len() as i128)),
+        Value::Struct(s) if s.has_method("__len__") =>
+            match s.call_method("__len__", vec![], &context.env, &context.printer)? {
+                Some(Value::Integer(len)) => context.output.send(Value::Integer(len)),
+                _ => argument_error("Expected __len__ to return an integer"),
+            }
         v =>
             match v.readable() {
                 Some(readable) => context.output.send(count_rows(readable)),
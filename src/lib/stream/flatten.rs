@@ -0,0 +1,116 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, error, argument_error};
+use crate::lang::{table::Row, value::Value, value::ValueType, argument::Argument};
+use crate::lang::stream::{Readable, ValueSender};
+use crate::lang::table::{ColumnType, ColumnVec};
+
+/**
+  The only column of the input that contains lists or tables, if there is exactly one.
+*/
+fn guess_column(input_type: &[ColumnType]) -> CrushResult<usize> {
+    let candidates = input_type.iter()
+        .enumerate()
+        .filter(|(_, t)| matches!(t.cell_type, ValueType::List(_) | ValueType::Table(_) | ValueType::TableStream(_)))
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    match candidates.len() {
+        1 => Ok(candidates[0]),
+        n => argument_error(
+            format!("Could not guess column to flatten, expected one list or table column, found {}", n).as_str()),
+    }
+}
+
+fn parse(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<usize> {
+    match arguments.len() {
+        0 => guess_column(input_type),
+        1 => match &arguments[0].value {
+            Value::Field(f) => input_type.find(f),
+            _ => argument_error("Expected the field to flatten"),
+        },
+        _ => argument_error("Expected at most one field to flatten"),
+    }
+}
+
+/**
+  The columns that replace the flattened one. Columns of nested tables and structs that clash
+  with the outer columns are prefixed with the name of the flattened column.
+*/
+fn inner_type(input_type: &[ColumnType], idx: usize, first: Option<&Row>) -> CrushResult<Vec<ColumnType>> {
+    let column = &input_type[idx];
+    let inner = match &column.cell_type {
+        ValueType::List(element_type) => return Ok(vec![ColumnType::new(&column.name, *element_type.clone())]),
+        ValueType::Table(t) | ValueType::TableStream(t) => t.clone(),
+        ValueType::Struct => match first.map(|r| &r.cells()[idx]) {
+            Some(Value::Struct(s)) => s.local_signature(),
+            _ => Vec::new(),
+        },
+        _ => return argument_error("Expected a list, table or struct column"),
+    };
+    Ok(inner.into_iter()
+        .map(|t| if input_type.iter().any(|o| o.name == t.name) {
+            ColumnType::new(&format!("{}_{}", column.name, t.name), t.cell_type)
+        } else {
+            t
+        })
+        .collect())
+}
+
+/**
+  The values of the flattened cell, one vector per output row.
+*/
+fn expand(cell: Value, inner_type: &[ColumnType]) -> CrushResult<Vec<Vec<Value>>> {
+    match cell {
+        Value::List(l) => Ok(l.dump().into_iter().map(|v| vec![v]).collect()),
+        Value::Struct(s) => {
+            let signature = s.local_signature();
+            if signature.len() != inner_type.len()
+                || signature.iter().zip(inner_type.iter()).any(|(a, b)| a.cell_type != b.cell_type) {
+                return error("All structs must have the same fields in order to be flattened");
+            }
+            Ok(vec![s.local_elements().into_iter().map(|(_, v)| v).collect()])
+        }
+        value => match value.readable() {
+            Some(mut r) => {
+                let mut res = Vec::new();
+                while let Ok(row) = r.read() {
+                    res.push(row.into_vec());
+                }
+                Ok(res)
+            }
+            None => error("Expected a list, table or struct"),
+        },
+    }
+}
+
+pub fn run(idx: usize, input: &mut dyn Readable, sender: ValueSender) -> CrushResult<()> {
+    let input_type = input.types().to_vec();
+    let first = input.read().ok();
+    let inner_type = inner_type(&input_type, idx, first.as_ref())?;
+
+    let mut output_type = input_type.clone();
+    output_type.splice(idx..idx + 1, inner_type.clone());
+    let output = sender.initialize(output_type)?;
+
+    let mut next = first;
+    while let Some(row) = next {
+        let mut cells = row.into_vec();
+        let cell = cells.remove(idx);
+        for inner in expand(cell, &inner_type)? {
+            let mut out = cells.clone();
+            out.splice(idx..idx, inner);
+            output.send(Row::new(out))?;
+        }
+        next = input.read().ok();
+    }
+    Ok(())
+}
+
+pub fn perform(context: ExecutionContext) -> CrushResult<()> {
+    match context.input.recv()?.readable() {
+        Some(mut r) => {
+            let idx = parse(r.types(), context.arguments)?;
+            run(idx, r.as_mut(), context.output)
+        }
+        None => error("Expected a stream"),
+    }
+}
@@ -1,83 +1,429 @@
-use crate::lang::execution_context::{ExecutionContext, ArgumentVector};
+use crate::lang::execution_context::ExecutionContext;
 use std::collections::HashMap;
 use crate::{
     lang::errors::argument_error,
     lang::{
         argument::Argument,
         table::Row,
+        table::Table,
         value::ValueType,
         value::Value,
+        value::ValueDefinition,
     },
-    lang::stream::{OutputStream, unlimited_streams},
+    lang::stream::{OutputStream, ValueSender, unlimited_streams, channels, empty_channel, black_hole},
 };
 use crate::lang::{table::ColumnType};
-use crate::lang::errors::{CrushResult, error};
+use crate::lang::errors::{CrushResult, error, mandate};
 use crate::lang::stream::Readable;
 use crate::lang::table::ColumnVec;
+use crate::lang::command::CrushCommand;
+use crate::lang::scope::Scope;
+use super::aggr::{Aggregation, run_aggregations, workers};
+use super::sum_avg::{Accumulator, Function};
+use std::sync::Arc;
+
+enum Key {
+    Column(usize),
+    Closure(Box<dyn CrushCommand + Send + Sync>),
+}
+
+/**
+  Simple aggregations, like {sum ^size}, are computed while grouping. Other commands are run
+  with the rows of each group as their input once all groups are complete.
+*/
+enum Output {
+    Function(Function, Option<usize>),
+    Command(usize),
+}
 
 pub struct Config {
     name: String,
-    column: usize,
+    keys: Vec<Key>,
+    outputs: Vec<(String, Output)>,
+    commands: Vec<Aggregation>,
+}
+
+fn column(input_type: &[ColumnType], value: &Value) -> CrushResult<Option<usize>> {
+    match value {
+        Value::String(cell_name) => Ok(Some(input_type.find_str(cell_name)?)),
+        Value::Field(cell_name) => Ok(Some(input_type.find(cell_name)?)),
+        _ => Ok(None),
+    }
+}
+
+/**
+  Recognize closures that call count, sum, avg, min or max, with at most a column as argument.
+*/
+fn function(
+    command: &(dyn CrushCommand + Send + Sync),
+    input_type: &[ColumnType],
+    env: &Scope) -> CrushResult<Option<(Function, Option<usize>)>> {
+    let invocation = match command.jobs() {
+        Some([job]) => match job.commands() {
+            [invocation] => invocation,
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let function = match invocation.command() {
+        ValueDefinition::Label(name) => match (Function::parse(name), env.get(name)?) {
+            (Some(function), Some(Value::Command(c))) if c.jobs().is_none() => function,
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let column = match invocation.arguments() {
+        [] if function == Function::Count => None,
+        [] if input_type.len() == 1 => Some(0),
+        [argument] if !argument.argument_type.is_some() => match &argument.value {
+            ValueDefinition::Value(Value::Field(f)) => Some(input_type.find(f)?),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    if let Some(idx) = column {
+        function.check(&input_type[idx].cell_type)?;
+    }
+    Ok(Some((function, column)))
 }
 
-pub fn parse(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<Config> {
-    arguments.check_len(1)?;
-    let arg = &arguments[0];
-    let name = arg.argument_type.clone().unwrap_or_else(|| "group".to_string());
-    match &arg.value {
-        Value::String(cell_name) =>
-            Ok(Config {
-                column: input_type.find_str(cell_name)?,
-                name,
-            }),
-        Value::Field(cell_name) =>
-            Ok(Config {
-                column: input_type.find(cell_name)?,
-                name,
-            }),
-        _ => argument_error("Bad comparison key"),
+/**
+  An aggregation given by name, like "count" or "sum". Functions other than count need a column,
+  so they can only be named like this if the input has a single column.
+*/
+fn named_function(name: &str, input_type: &[ColumnType]) -> CrushResult<Option<(Function, Option<usize>)>> {
+    match Function::parse(name) {
+        None => Ok(None),
+        Some(Function::Count) => Ok(Some((Function::Count, None))),
+        Some(f) if input_type.len() == 1 => {
+            f.check(&input_type[0].cell_type)?;
+            Ok(Some((f, Some(0))))
+        }
+        Some(f) => argument_error(
+            format!("{} needs a column, use e.g. {{{} ^size}}", f.name(), f.name()).as_str()),
+    }
+}
+
+pub fn parse(input_type: &[ColumnType], arguments: Vec<Argument>, env: &Scope) -> CrushResult<Config> {
+    let mut name = None;
+    let mut keys = Vec::new();
+    let mut outputs = Vec::new();
+    let mut commands = Vec::new();
+    for arg in arguments {
+        if let (Some(n), Value::String(s)) = (&arg.argument_type, &arg.value) {
+            if input_type.find_str(s).is_err() {
+                match named_function(s, input_type)? {
+                    Some((f, column)) => {
+                        outputs.push((n.clone(), Output::Function(f, column)));
+                        continue;
+                    }
+                    None => return argument_error(format!("Unknown column or aggregation {}", s).as_str()),
+                }
+            }
+        }
+        match (arg.argument_type, column(input_type, &arg.value)?, arg.value) {
+            (None, Some(idx), _) => keys.push(Key::Column(idx)),
+            (None, None, Value::Command(c)) => keys.push(Key::Closure(c)),
+            (None, None, _) => return argument_error("Expected a column or a closure as group key"),
+            (Some(n), Some(idx), _) => {
+                name = Some(n);
+                keys.push(Key::Column(idx));
+            }
+            (Some(n), None, Value::Command(command)) => match function(command.as_ref(), input_type, env)? {
+                Some((f, column)) => outputs.push((n, Output::Function(f, column))),
+                None => {
+                    outputs.push((n.clone(), Output::Command(commands.len())));
+                    commands.push(Aggregation { name: n, command });
+                }
+            },
+            (Some(n), None, _) => return argument_error(
+                format!(
+                    "Expected a closure like {{sum ^size}} or the name of an aggregation like \"count\" for {}. \
+                    Commands that are not in a closure, like {}=count, are run right away",
+                    n, n).as_str()),
+        }
+    }
+    if keys.is_empty() {
+        return argument_error("No group key specified");
     }
+    Ok(Config {
+        name: name.unwrap_or_else(|| "group".to_string()),
+        keys,
+        outputs,
+        commands,
+    })
+}
+
+/**
+  Computed keys are called with the cells of the row as named arguments, like the condition
+  of where.
+*/
+fn evaluate(
+    command: &(dyn CrushCommand + Send + Sync),
+    row: &Row,
+    input_type: &[ColumnType],
+    context: &ExecutionContext) -> CrushResult<Value> {
+    let arguments = row.cells().iter()
+        .zip(input_type.iter())
+        .map(|(c, t)| Argument::named(t.name.as_ref(), c.clone()))
+        .collect();
+    let (sender, receiver) = channels();
+    command.invoke(context.clone().with_args(arguments, None).with_sender(sender))?;
+    mandate(receiver.recv().ok(), "Group key did not return a value")
+}
+
+fn key(row: &Row, config: &Config, input_type: &[ColumnType], context: &ExecutionContext) -> CrushResult<Vec<Value>> {
+    config.keys.iter()
+        .map(|k| match k {
+            Key::Column(idx) => Ok(row.cells()[*idx].clone()),
+            Key::Closure(c) => evaluate(c.as_ref(), row, input_type, context),
+        })
+        .collect()
+}
+
+/**
+  Key columns are named after the column they come from, computed keys are named key, key2,
+  and so on.
+*/
+fn key_type(config: &Config, input_type: &[ColumnType], first: Option<&[Value]>) -> Vec<ColumnType> {
+    let mut computed = 0;
+    config.keys.iter()
+        .enumerate()
+        .map(|(idx, k)| match k {
+            Key::Column(column) => input_type[*column].clone(),
+            Key::Closure(_) => {
+                computed += 1;
+                let name = if computed == 1 { "key".to_string() } else { format!("key{}", computed) };
+                ColumnType::new(
+                    &name,
+                    first.map(|f| f[idx].value_type()).unwrap_or(ValueType::Any))
+            }
+        })
+        .collect()
 }
 
+/**
+  Without aggregations, the rows of a group are sent to its nested table. With aggregations,
+  the accumulators of the group are updated while the rows arrive, and the rows are only kept
+  if there are commands to run on them.
+*/
+enum Rows {
+    Table(OutputStream),
+    Aggregations(Vec<Accumulator>, Vec<Row>),
+}
+
+struct Group {
+    key: Vec<Value>,
+    rows: Rows,
+}
+
+/**
+  The groups seen so far. Keys that can not be hashed, like structs with an `__eq__` method,
+  are compared one by one.
+*/
+struct Groups {
+    groups: Vec<Group>,
+    index: HashMap<Vec<Value>, usize>,
+}
+
+impl Groups {
+    fn find(&self, key: &[Value], context: &ExecutionContext) -> CrushResult<Option<usize>> {
+        if key.iter().all(|k| k.value_type().is_hashable()) {
+            return Ok(self.index.get(key).cloned());
+        }
+        for (idx, group) in self.groups.iter().enumerate() {
+            let mut equal = true;
+            for (a, b) in group.key.iter().zip(key.iter()) {
+                if !a.equals(b, &context.env, &context.printer)? {
+                    equal = false;
+                    break;
+                }
+            }
+            if equal {
+                return Ok(Some(idx));
+            }
+        }
+        Ok(None)
+    }
+
+    fn insert(&mut self, group: Group) -> usize {
+        if group.key.iter().all(|k| k.value_type().is_hashable()) {
+            self.index.insert(group.key.clone(), self.groups.len());
+        }
+        self.groups.push(group);
+        self.groups.len() - 1
+    }
+}
+
+fn accumulators(config: &Config, input_type: &[ColumnType]) -> CrushResult<Vec<Accumulator>> {
+    config.outputs.iter()
+        .filter_map(|(_, o)| match o {
+            Output::Function(f, column) => Some(Accumulator::new(
+                *f,
+                &column.map(|idx| input_type[idx].cell_type.clone()).unwrap_or(ValueType::Any))),
+            Output::Command(_) => None,
+        })
+        .collect()
+}
+
+fn update(accumulators: &mut [Accumulator], config: &Config, row: &Row) -> CrushResult<()> {
+    let functions = config.outputs.iter().filter_map(|(_, o)| match o {
+        Output::Function(_, column) => Some(column),
+        Output::Command(_) => None,
+    });
+    for (accumulator, column) in accumulators.iter_mut().zip(functions) {
+        accumulator.add(column.map(|idx| &row.cells()[idx]).unwrap_or(&Value::Empty()))?;
+    }
+    Ok(())
+}
+
+fn output_type(config: &Config, input_type: &[ColumnType], first: Option<&Row>) -> Vec<ColumnType> {
+    let mut res = key_type(config, input_type, first.map(|r| &r.cells()[0..config.keys.len()]));
+    for (idx, (name, output)) in config.outputs.iter().enumerate() {
+        let cell_type = match output {
+            Output::Function(Function::Count, _) => ValueType::Integer,
+            Output::Function(_, Some(column)) => input_type[*column].cell_type.clone(),
+            _ => first
+                .map(|r| r.cells()[config.keys.len() + idx].value_type())
+                .unwrap_or(ValueType::Any),
+        };
+        res.push(ColumnType::new(name, cell_type));
+    }
+    res
+}
+
+/**
+  Compute the output row of a group, running the commands on its rows.
+*/
+fn finish(
+    key: Vec<Value>,
+    accumulators: Vec<Accumulator>,
+    rows: Vec<Row>,
+    config: &Config,
+    input_type: &[ColumnType],
+    context: &ExecutionContext) -> CrushResult<Row> {
+    let mut commands = if config.commands.is_empty() {
+        Vec::new()
+    } else {
+        run_aggregations(Value::Table(Table::new(input_type.to_vec(), rows)), &config.commands, context)?
+    };
+    let mut accumulators = accumulators.into_iter();
+    let mut cells = key;
+    for (_, output) in config.outputs.iter() {
+        cells.push(match output {
+            Output::Function(_, _) => mandate(accumulators.next(), "Missing aggregation")?.value()?,
+            Output::Command(idx) => std::mem::replace(&mut commands[*idx], Value::Empty()),
+        });
+    }
+    Ok(Row::new(cells))
+}
+
+/**
+  Without aggregations, every group is output as soon as its first row is seen, and the rows
+  of the group are streamed into its nested table. The nested tables are unbounded, since a
+  group is only complete once all of the input has been read.
+*/
 pub fn run(
     config: Config,
     input_type: &[ColumnType],
     input: &mut dyn Readable,
-    output: OutputStream,
+    sender: ValueSender,
+    context: &ExecutionContext,
 ) -> CrushResult<()> {
-    let mut groups: HashMap<Value, OutputStream> = HashMap::new();
+    let streaming = config.outputs.is_empty();
+    let keep_rows = !config.commands.is_empty();
+    let mut groups = Groups { groups: Vec::new(), index: HashMap::new() };
+    let mut output = None;
 
     while let Ok(row) = input.read() {
-        let key = row.cells()[config.column].clone();
-        let val = groups.get(&key);
-        match val {
-            None => {
-                let (output_stream, input_stream) = unlimited_streams(input_type.to_vec());
-                let out_row = Row::new(vec![key.clone(), Value::TableStream(input_stream)]);
-                output.send(out_row)?;
-                let _ = output_stream.send(row);
-                groups.insert(key, output_stream);
+        let key = key(&row, &config, input_type, context)?;
+        let idx = match groups.find(&key, context)? {
+            Some(idx) => idx,
+            None => if streaming {
+                let (group_output, group_input) = unlimited_streams(input_type.to_vec());
+                if output.is_none() {
+                    let mut output_type = key_type(&config, input_type, Some(&key));
+                    output_type.push(ColumnType::new(&config.name, ValueType::TableStream(input_type.to_vec())));
+                    output = Some(sender.initialize(output_type)?);
+                }
+                let mut cells = key.clone();
+                cells.push(Value::TableStream(group_input));
+                if let Some(o) = &output {
+                    o.send(Row::new(cells))?;
+                }
+                groups.insert(Group { key, rows: Rows::Table(group_output) })
+            } else {
+                let accumulators = accumulators(&config, input_type)?;
+                groups.insert(Group { key, rows: Rows::Aggregations(accumulators, Vec::new()) })
+            }
+        };
+        match &mut groups.groups[idx].rows {
+            Rows::Table(o) => {
+                let _ = o.send(row);
             }
-            Some(output_stream) => {
-                let _ = output_stream.send(row);
+            Rows::Aggregations(accumulators, rows) => {
+                update(accumulators, &config, &row)?;
+                if keep_rows {
+                    rows.push(row);
+                }
             }
         }
     }
+
+    if streaming {
+        if output.is_none() {
+            let mut output_type = key_type(&config, input_type, None);
+            output_type.push(ColumnType::new(&config.name, ValueType::TableStream(input_type.to_vec())));
+            sender.initialize(output_type)?;
+        }
+        return Ok(());
+    }
+
+    /* The commands of different groups run in parallel, on a fixed number of threads. */
+    let config = Arc::new(config);
+    let worker_config = config.clone();
+    let worker_type = input_type.to_vec();
+    let worker_context = context.clone();
+    let (mut jobs, results) = workers(
+        "group",
+        move |(key, accumulators, rows)| finish(key, accumulators, rows, &worker_config, &worker_type, &worker_context))?;
+    for group in groups.groups {
+        if let Rows::Aggregations(accumulators, rows) = group.rows {
+            jobs.send((group.key, accumulators, rows))?;
+        }
+    }
+    drop(jobs);
+
+    let mut output = None;
+    for row in results {
+        let row = row?;
+        if output.is_none() {
+            output = Some(sender.initialize(output_type(&config, input_type, Some(&row)))?);
+        }
+        if let Some(o) = &output {
+            o.send(row)?;
+        }
+    }
+    if output.is_none() {
+        sender.initialize(output_type(&config, input_type, None))?;
+    }
     Ok(())
 }
 
 pub fn perform(context: ExecutionContext) -> CrushResult<()> {
     match context.input.recv()?.readable() {
         Some(mut input) => {
-            let config = parse(input.types(), context.arguments)?;
-            let output_type = vec![
-                input.types()[config.column].clone(),
-                ColumnType::new(
-                    &config.name,
-                    ValueType::TableStream(input.types().to_vec()))
-            ];
-            let output = context.output.initialize(output_type)?;
-            run(config, &input.types().to_vec(), input.as_mut(), output)
+            let input_type = input.types().to_vec();
+            let config = parse(&input_type, context.arguments, &context.env)?;
+            let base_context = ExecutionContext {
+                input: empty_channel(),
+                output: black_hole(),
+                arguments: vec![],
+                env: context.env,
+                this: None,
+                printer: context.printer,
+            };
+            run(config, &input_type, input.as_mut(), context.output, &base_context)
         }
         None => error("Expected a stream"),
     }
@@ -1,174 +1,261 @@
-use crate::lang::execution_context::{ExecutionContext, ArgumentVector};
-use crate::lang::errors::CrushResult;
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, mandate};
 use std::collections::HashMap;
 use crate::lang::stream::Readable;
-use crate::lang::errors::CrushError;
 use crate::lang::table::Row;
 use crate::lang::table::ColumnType;
 use crate::lang::value::ValueType;
 use crate::lang::value::Value;
 use crate::lang::stream::OutputStream;
-use crate::util::replace::Replace;
 use crate::lang::errors::argument_error;
-use crate::lang::r#struct::Struct;
 use crate::lang::table::ColumnVec;
 use crate::lang::argument::Argument;
 use crate::lang::printer::Printer;
+use crate::lang::scope::Scope;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Kind {
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+impl Kind {
+    fn keep_left(self) -> bool {
+        self == Kind::Left || self == Kind::Full
+    }
+
+    fn keep_right(self) -> bool {
+        self == Kind::Right || self == Kind::Full
+    }
+}
 
 pub struct Config {
-    left_table_idx: usize,
-    right_table_idx: usize,
-    left_column_idx: usize,
-    right_column_idx: usize,
+    kind: Kind,
+    left_types: Vec<ColumnType>,
+    right_types: Vec<ColumnType>,
+    left_columns: Vec<usize>,
+    right_columns: Vec<usize>,
 }
 
-pub fn get_sub_type(cell_type: &ValueType) -> Result<&[ColumnType], CrushError> {
-    match cell_type {
+/**
+  The two tables to join, together with the names used to refer to them in key fields.
+*/
+struct Tables {
+    left: (String, Value),
+    right: (String, Value),
+}
+
+fn table_types(value: &Value) -> CrushResult<Vec<ColumnType>> {
+    match value.value_type() {
         ValueType::TableStream(sub_types) | ValueType::Table(sub_types) => Ok(sub_types),
-        _ => argument_error("Expected a table column"),
+        _ => argument_error("Expected a table"),
     }
 }
 
-pub fn guess_tables(input_type: &[ColumnType]) -> Result<(usize, usize, &[ColumnType], &[ColumnType]), CrushError> {
-    let tables: Vec<(usize, &Vec<ColumnType>)> = input_type.iter().enumerate().flat_map(|(idx, t)| {
-        match &t.cell_type {
-            ValueType::TableStream(sub_types) | ValueType::Table(sub_types) => Some((idx, sub_types)),
-            _ => None,
+/**
+  Pick the tables to join from the cells of the input struct. Tables are either named by the
+  first pair of keys, e.g. ^l:name ^r:name, or they are the only two tables in the struct.
+*/
+fn find_tables(elements: Vec<(String, Value)>, keys: &[Vec<String>]) -> CrushResult<Tables> {
+    let mut tables = elements.into_iter()
+        .filter(|(_, v)| table_types(v).is_ok())
+        .collect::<Vec<_>>();
+    match keys {
+        [l, r, ..] if l.len() == 2 && r.len() == 2 => {
+            if l[0] == r[0] {
+                return argument_error("Left and right table can't be the same");
+            }
+            let take = |tables: &mut Vec<(String, Value)>, name: &str| {
+                let idx = tables.iter().position(|(n, _)| n == name);
+                mandate(idx, format!("Unknown table {}", name).as_str()).map(|idx| tables.remove(idx))
+            };
+            let left = take(&mut tables, &l[0])?;
+            let right = take(&mut tables, &r[0])?;
+            Ok(Tables { left, right })
+        }
+        _ => {
+            if tables.len() != 2 {
+                return argument_error(
+                    format!("Could not guess tables to join, expected two tables, found {}", tables.len()).as_str());
+            }
+            let right = tables.remove(1);
+            let left = tables.remove(0);
+            Ok(Tables { left, right })
         }
-    }).collect();
-    if tables.len() == 2 {
-        Ok((tables[0].0, tables[1].0, tables[0].1, tables[1].1))
-    } else {
-        argument_error(format!("Could not guess tables to join, expected two tables, found {}", tables.len()).as_str())
     }
 }
 
-fn scan_table(table: &str, column: &str, input_type: &[ColumnType]) -> Result<(usize, usize), CrushError> {
-    let table_idx = input_type.find_str(&table.to_string())?;
-    let column_idx = get_sub_type(&input_type[table_idx].cell_type)?.find_str(&column.to_string())?;
-    Ok((table_idx, column_idx))
+fn key_column(field: &[String], table: &str, types: &[ColumnType]) -> CrushResult<usize> {
+    match field.len() {
+        1 => types.find_str(&field[0]),
+        2 if field[0] == table => types.find_str(&field[1]),
+        2 => argument_error(format!("Expected a column of table {}", table).as_str()),
+        _ => argument_error("Expected fields on the form ^table:column or ^column"),
+    }
 }
 
-fn parse(input_type: &[ColumnType], arguments: Vec<Argument>) -> Result<Config, CrushError> {
-    arguments.check_len(2)?;
+fn parse(input: Option<Value>, arguments: Vec<Argument>) -> CrushResult<(Config, Tables)> {
+    let mut left = None;
+    let mut right = None;
+    let mut kind = Kind::Inner;
+    let mut keys = Vec::new();
+    let mut shared_keys = Vec::new();
 
-    match (&arguments[0].value, &arguments[1].value) {
-        (Value::Field(l), Value::Field(r)) => {
-            let config = match (l.len(), r.len()) {
-                (1, 1) => {
-                    let (left_table_idx, right_table_idx, left_types, right_types) = guess_tables(&input_type)?;
-
-                    Config {
-                        left_table_idx,
-                        right_table_idx,
-                        left_column_idx: left_types.find(&l)?,
-                        right_column_idx: right_types.find(&r)?,
-                    }
-                }
-                (2, 2) => {
-                    let (left_table_idx, left_column_idx) =
-                        scan_table(l[0].as_ref(), l[1].as_ref(), &input_type)?;
-
-                    let (right_table_idx, right_column_idx) =
-                        scan_table(r[0].as_ref(), r[1].as_ref(), &input_type)?;
+    for arg in arguments {
+        match (arg.argument_type.as_deref(), arg.value) {
+            (None, Value::Field(f)) => keys.push(f),
+            (Some("on"), Value::Field(f)) => shared_keys.push(f),
+            (Some("left"), v) => left = Some(("left".to_string(), v)),
+            (Some("right"), v) => right = Some(("right".to_string(), v)),
+            (Some("type"), Value::String(s)) => kind = match s.as_ref() {
+                "inner" => Kind::Inner,
+                "left" => Kind::Left,
+                "right" => Kind::Right,
+                "full" => Kind::Full,
+                _ => return argument_error("Unknown join type, expected one of inner, left, right and full"),
+            },
+            _ => return argument_error("Expected key fields, the tables to join and the join type"),
+        }
+    }
+    if keys.len() % 2 != 0 {
+        return argument_error("Expected key fields in pairs, one for the left table and one for the right table");
+    }
+    if keys.is_empty() && shared_keys.is_empty() {
+        return argument_error("No join keys specified");
+    }
 
-                    if left_table_idx == right_table_idx {
-                        return argument_error("Left and right table can't be the same");
-                    }
+    let tables = match (left, right) {
+        (Some(left), Some(right)) => Tables { left, right },
+        (None, None) => match input {
+            Some(Value::Struct(s)) => find_tables(s.local_elements(), &keys)?,
+            _ => return argument_error("Expected a struct containing the tables to join"),
+        },
+        _ => return argument_error("Both the left and the right table must be given"),
+    };
 
-                    Config {
-                        left_table_idx,
-                        right_table_idx,
-                        left_column_idx,
-                        right_column_idx,
-                    }
-                }
-                _ => return argument_error("Expected both fields on the form %table.column or %column"),
-            };
+    let left_types = table_types(&tables.left.1)?;
+    let right_types = table_types(&tables.right.1)?;
+    let mut left_columns = Vec::new();
+    let mut right_columns = Vec::new();
+    for pair in keys.chunks(2) {
+        left_columns.push(key_column(&pair[0], &tables.left.0, &left_types)?);
+        right_columns.push(key_column(&pair[1], &tables.right.0, &right_types)?);
+    }
+    for key in &shared_keys {
+        left_columns.push(key_column(key, &tables.left.0, &left_types)?);
+        right_columns.push(key_column(key, &tables.right.0, &right_types)?);
+    }
 
-            let r_type = &get_sub_type(&input_type[config.right_table_idx].cell_type)?[config.right_column_idx].cell_type;
-            let l_type = &get_sub_type(&input_type[config.left_table_idx].cell_type)?[config.left_column_idx].cell_type;
-            if r_type != l_type {
-                return argument_error("Cannot join two columns of different types");
-            }
-            if !r_type.is_hashable() {
-                argument_error("Cannot join on this column type. (It is either mutable or not comparable)")
-            } else {
-                Ok(config)
-            }
+    for (l, r) in left_columns.iter().zip(right_columns.iter()) {
+        let l_type = &left_types[*l].cell_type;
+        if *l_type != right_types[*r].cell_type {
+            return argument_error("Cannot join two columns of different types");
+        }
+        if !l_type.is_hashable() && *l_type != ValueType::Struct {
+            return argument_error("Cannot join on this column type. (It is either mutable or not comparable)");
         }
-        _ => argument_error("Expected arguments like %table1.col == %table2.col"),
     }
+
+    Ok((Config { kind, left_types, right_types, left_columns, right_columns }, tables))
 }
 
-fn combine(mut l: Row, r: Row, cfg: &Config) -> Row {
-    for (idx, c) in r.into_vec().drain(..).enumerate() {
-        if idx != cfg.right_column_idx {
-            l.push(c);
+/**
+  The left key columns are shared by both sides. Columns of a side that may be missing from the
+  output keep their type and hold empty values where there is no match.
+*/
+fn output_type(cfg: &Config) -> Vec<ColumnType> {
+    let mut res = cfg.left_types.clone();
+    for (idx, c) in cfg.right_types.iter().enumerate() {
+        if !cfg.right_columns.contains(&idx) {
+            res.push(c.clone());
         }
     }
-    l
+    res
 }
 
-fn do_join(cfg: &Config, l: &mut dyn Readable, r: &mut dyn Readable, output: &OutputStream, printer: &Printer) -> CrushResult<()> {
-    let mut l_data: HashMap<Value, Row> = HashMap::new();
-    while let Ok(row) = l.read() {
-        l_data.insert(row.cells()[cfg.left_column_idx].clone(), row);
-    }
-
-    while let Ok(r_row) = r.read() {
-        l_data.remove(&r_row.cells()[cfg.right_column_idx])
-            .map(|l_row| {
-                printer.handle_error(output.send(combine(l_row, r_row, cfg)));
-            });
+fn combine(l: Option<&Row>, r: Option<&Row>, cfg: &Config) -> Row {
+    let cells = match (l, r) {
+        (Some(l), _) => l.cells().clone(),
+        (None, Some(r)) => {
+            let mut cells = vec![Value::Empty(); cfg.left_types.len()];
+            for (l_idx, r_idx) in cfg.left_columns.iter().zip(cfg.right_columns.iter()) {
+                cells[*l_idx] = r.cells()[*r_idx].clone();
+            }
+            cells
+        }
+        (None, None) => vec![Value::Empty(); cfg.left_types.len()],
+    };
+    let mut row = Row::new(cells);
+    for idx in 0..cfg.right_types.len() {
+        if !cfg.right_columns.contains(&idx) {
+            row.push(r.map(|r| r.cells()[idx].clone()).unwrap_or(Value::Empty()));
+        }
     }
-    Ok(())
+    row
 }
 
-pub fn run(
-    config: Config,
-    row: Struct,
-    output: OutputStream,
-    printer: &Printer,
-) -> CrushResult<()> {
-    let mut v = row.to_vec();
-    match (v.replace(config.left_table_idx, Value::Integer(0)).readable(), v.replace(config.right_table_idx, Value::Integer(0)).readable()) {
-        (Some(mut l), Some(mut r)) =>
-            do_join(&config, l.as_mut(), r.as_mut(), &output, printer),
-        _ => panic!("Wrong row format"),
-    }
+fn key(row: &Row, columns: &[usize]) -> Vec<Value> {
+    columns.iter().map(|idx| row.cells()[*idx].clone()).collect()
 }
 
-fn get_output_type(input_type: &[ColumnType], cfg: &Config) -> Result<Vec<ColumnType>, CrushError> {
-    let tables: Vec<Option<&Vec<ColumnType>>> = input_type.iter().map(|t| {
-        match &t.cell_type {
-            ValueType::TableStream(sub_types) | ValueType::Table(sub_types) => Some(sub_types),
-            _ => None,
+/**
+  The left table is kept in memory, indexed by key, while the right table is streamed.
+  Keys that can not be hashed, like structs with an `__eq__` method, are compared one by one.
+*/
+fn do_join(cfg: &Config, l: &mut dyn Readable, r: &mut dyn Readable, output: &OutputStream, env: &Scope, printer: &Printer) -> CrushResult<()> {
+    let hashable = cfg.left_columns.iter().all(|idx| cfg.left_types[*idx].cell_type.is_hashable());
+    let mut l_data: Vec<Row> = Vec::new();
+    let mut index: HashMap<Vec<Value>, Vec<usize>> = HashMap::new();
+    while let Ok(row) = l.read() {
+        if hashable {
+            index.entry(key(&row, &cfg.left_columns)).or_default().push(l_data.len());
         }
-    }).collect();
-
-    match (tables[cfg.left_table_idx], tables[cfg.right_table_idx]) {
-        (Some(v1), Some(v2)) => {
-            let mut res = v1.clone();
-            for (idx, c) in v2.iter().enumerate() {
-                if idx != cfg.right_column_idx {
-                    res.push(c.clone());
+        l_data.push(row);
+    }
+    let mut matched = vec![false; l_data.len()];
+
+    while let Ok(r_row) = r.read() {
+        let r_key = key(&r_row, &cfg.right_columns);
+        let matches = if hashable {
+            index.get(&r_key).cloned().unwrap_or_default()
+        } else {
+            let mut res = Vec::new();
+            'rows: for (idx, l_row) in l_data.iter().enumerate() {
+                for (l_idx, r_value) in cfg.left_columns.iter().zip(r_key.iter()) {
+                    if !l_row.cells()[*l_idx].equals(r_value, env, printer)? {
+                        continue 'rows;
+                    }
                 }
+                res.push(idx);
             }
-            Ok(res)
+            res
+        };
+        if matches.is_empty() && cfg.kind.keep_right() {
+            output.send(combine(None, Some(&r_row), cfg))?;
+        }
+        for idx in matches {
+            matched[idx] = true;
+            output.send(combine(Some(&l_data[idx]), Some(&r_row), cfg))?;
         }
-        _ => argument_error("Impossible error?"),
     }
+
+    if cfg.kind.keep_left() {
+        for (row, _) in l_data.iter().zip(matched.iter()).filter(|(_, m)| !**m) {
+            output.send(combine(Some(row), None, cfg))?;
+        }
+    }
+    Ok(())
 }
 
 pub fn perform(context: ExecutionContext) -> CrushResult<()> {
-    match context.input.recv()? {
-        Value::Struct(s) => {
-            let cfg = parse(&s.local_signature(), context.arguments)?;
-            let output_type = get_output_type(&s.local_signature(), &cfg)?;
-            let output = context.output.initialize(output_type)?;
-            run(cfg, s, output, &context.printer)
-        }
-        _ => argument_error("Expected a struct"),
+    let input = context.input.recv().ok();
+    let (cfg, tables) = parse(input, context.arguments)?;
+    let output = context.output.initialize(output_type(&cfg))?;
+    match (tables.left.1.readable(), tables.right.1.readable()) {
+        (Some(mut l), Some(mut r)) =>
+            do_join(&cfg, l.as_mut(), r.as_mut(), &output, &context.env, &context.printer),
+        _ => argument_error("Expected two tables to join"),
     }
 }
@@ -14,11 +14,18 @@ mod uniq;
 mod group;
 mod join;
 mod zip;
-//mod aggr;
+mod aggr;
+mod reject;
+mod rename;
+mod retype;
+mod flatten;
+mod pivot;
+mod window;
 
 mod count;
 mod sum_avg;
 mod seq;
+mod tee;
 
 pub fn declare(root: &Scope) -> CrushResult<()> {
     let e = root.create_lazy_namespace(
@@ -42,22 +49,86 @@ pub fn declare(root: &Scope) -> CrushResult<()> {
     ps | where {$status != "Sleeping"}"#))?;
             env.declare_command(
                 "sort", sort::perform, true,
-                "sort column:field", "Sort input based on column", example!("ps | sort ^cpu"))?;
+                "sort [desc=]key:(field|command)... [compare=comparator:command] [--reverse] [--natural]",
+                "Sort input based on one or more keys",
+                Some(r#"    Rows are sorted by the first key, then by the second key and so on. A key is
+    either a column or a closure that computes a value from the cells of the row.
+    Keys given as desc=key sort in descending order. If the input has a single
+    column, the key can be omitted. The sort is stable.
+
+    A comparator closure is called with two rows as the structs a and b, and
+    returns true if a should come before b. It can also return an integer that
+    is negative, zero or positive. It is used for rows with equal keys.
+
+    If --reverse is given, the order of the output is reversed. If --natural is
+    given, numbers inside strings are compared by value, so that file2 comes
+    before file10.
+
+    Examples:
+
+    ps | sort ^user desc=^cpu
+    ls | sort {name:len} --natural
+    ps | sort compare={a:cpu > b:cpu}"#))?;
             env.declare_command(
                 "reverse", reverse::reverse, true,
                 "reverse", "Reverses the order of the rows in the input", None)?;
             env.declare_command(
                 "group", group::perform, true,
-                "group group=field|string", "Group input by the specified column", None)?;
+                "group [name=]key:(field|string|command)... name=aggregation:(command|string)...",
+                "Group input by the specified keys",
+                Some(r#"    Keys are either columns of the input or closures that compute a key from the
+    cells of a row. Each row of the output contains the key and a table with all
+    input rows for that key. The table is named group, unless a name is given,
+    e.g. files=^user.
+
+    If named aggregations are given, their results are output instead of the
+    tables. Closures that call count, sum, avg, min or max, e.g. {sum ^size}, and
+    those functions given by name, e.g. "count", are computed while grouping. Other
+    closures are called with the rows of every group as their input once all of
+    the input has been read.
+
+    Examples:
+
+    ps | group ^user ^status
+    ps | group {cpu > 10.0} count={count}
+    find . | group ^user count={count} total={sum ^size} biggest={max ^size}"#))?;
             env.declare_command(
                 "join", join::perform, true,
-                "join left:field right:field", "Join two streams together on the specified keys", None)?;
+                "join [left=table right=table] (left_key:field right_key:field)... [on=key:field]... [type=type:string]",
+                "Join two streams together on the specified keys",
+                Some(r#"    The two tables are either given as the left and right arguments, or read as
+    a struct from the input. Keys are given in pairs, first the column of the left
+    table and then the column of the right table, e.g. ^l:name ^r:user. A key that
+    has the same name in both tables can be given as on=^name. Multiple keys can
+    be given.
+
+    The type of join is one of inner (the default), left, right and full. Outer
+    joins also output the rows of the left, right or both tables that have no
+    match, with empty cells for the missing side.
+
+    The left table is read into memory, while the right table is streamed.
+
+    Examples:
+
+    data l=home r=age | join ^l:name ^r:name
+    join left=home right=age on=^name type="full""#))?;
             env.declare_command(
                 "uniq", uniq::uniq, true,
                 "uniq column:field",
                 "Only output the first row if multiple rows has the same value for the specified column",
                 example!("ps | uniq ^user"))?;
-            //env.declare_str("aggr", Value::Command(CrushCommand::command_undocumented(aggr::perform)))?;
+            env.declare_command(
+                "aggr", aggr::perform, true,
+                "aggr [column=table:field] name=aggregation:command...",
+                "Aggregate the tables in the input into single values",
+                Some(r#"    Every named closure is run once per row, with the nested table of that row as
+    its input. The nested table is replaced by one column per closure, containing
+    its result. If the input only has one column containing tables, the column
+    argument can be omitted.
+
+    Example:
+
+    find . | group ^user | aggr files={count} size={sum ^size}"#))?;
             env.declare_command(
                 "count", count::perform, true,
                 "count",
@@ -87,6 +158,99 @@ pub fn declare(root: &Scope) -> CrushResult<()> {
                 "select copy_fields:field... [%] new_field=definition:command",
                 "Pass on some old fields and calculate new ones for each line of input",
                 example!(r#"ls | select ^user path={"{}/{}":format (pwd) file}"#))?;
+            env.declare_command(
+                "reject", reject::perform, true,
+                "reject @columns:field",
+                "Pass on all fields except the specified ones",
+                example!("ps | reject ^ppid ^status"))?;
+            env.declare_command(
+                "rename", rename::perform, true,
+                "rename old_name=new_name:field...",
+                "Rename columns of the input",
+                example!("ps | rename cpu=^cpu_percent"))?;
+            env.declare_command(
+                "retype", retype::perform, true,
+                "retype column=type:type...",
+                "Convert columns of the input to another type",
+                example!("columns sizes.txt | retype size=float"))?;
+            env.declare_command(
+                "flatten", flatten::perform, true,
+                "flatten [column:field]",
+                "Expand a column containing lists, tables or structs",
+                Some(r#"    Every element of a list and every row of a table becomes a row of its own,
+    with the other columns repeated. The columns of a nested table, and the
+    fields of a struct, become columns of the output. If the column is omitted,
+    the only column containing lists or tables is flattened.
+
+    Example:
+
+    ps | group ^user | flatten"#))?;
+            env.declare_command(
+                "pivot", pivot::pivot, true,
+                "pivot key=key:field value=value:field [aggregation=aggregation:command]",
+                "Turn the values of a column into columns of their own",
+                Some(r#"    Every distinct value of the key column becomes a column, containing the
+    corresponding values of the value column. The remaining columns identify the
+    rows of the output. Missing values are empty. The value columns have the type
+    of the value column, or of the aggregation results. Keys that would give two
+    columns the same name are an error.
+
+    If several rows have the same key, the aggregation is called with a table of
+    their values as its input. Without an aggregation, this is an error.
+
+    Example:
+
+    metrics | pivot key=^metric value=^value aggregation={avg ^value}"#))?;
+            env.declare_command(
+                "unpivot", pivot::unpivot, true,
+                "unpivot @columns:field [key=name:field] [value=name:field]",
+                "Turn columns into rows with a key and a value column",
+                Some(r#"    For every input row, one row is output per specified column, containing the
+    remaining columns, the name of the column and its value. The new columns are
+    named key and value, unless other names are given.
+
+    Example:
+
+    hosts | unpivot ^cpu ^memory key=^metric"#))?;
+            env.declare_command(
+                "window", window::window, true,
+                "window column:field [function=function:string] [rows=rows:integer] [span=span:duration time=time:field] [partition=partition:field] [name=name:string]",
+                "Add a column with a running or rolling computation over the specified column",
+                Some(r#"    The function is one of sum (the default), avg, min, max and count. Without
+    rows or span, it is computed over all rows seen so far. With rows, it is
+    computed over the last rows rows, and with span over the rows whose time
+    column is less than span older than the current row.
+
+    If a partition column is given, each distinct value of it gets a window of its
+    own. The new column is named after the column and the function, unless a name
+    is given.
+
+    Examples:
+
+    ps | window ^cpu
+    log | window ^bytes function="avg" span=(duration:new 5 "minutes") time=^timestamp partition=^host"#))?;
+            env.declare_command(
+                "lag", window::lag, true,
+                "lag column:field [rows=rows:integer] [partition=partition:field] [name=name:string]",
+                "Add a column with the value of the specified column some rows earlier",
+                Some(r#"    The number of rows defaults to one. The first rows get empty values. If a
+    partition column is given, only rows with the same value in that column are
+    considered.
+
+    Example:
+
+    measurements | lag ^value partition=^sensor"#))?;
+            env.declare_command(
+                "lead", window::lead, true,
+                "lead column:field [rows=rows:integer] [partition=partition:field] [name=name:string]",
+                "Add a column with the value of the specified column some rows later",
+                Some(r#"    The number of rows defaults to one. The last rows get empty values. If a
+    partition column is given, only rows with the same value in that column are
+    considered.
+
+    Example:
+
+    measurements | lead ^value rows=2"#))?;
             env.declare_command(
                 "enumerate", enumerate::perform, true,
                 "enumerate", "Prepend a column containing the row number to each row of the input", None)?;
@@ -96,9 +260,35 @@ pub fn declare(root: &Scope) -> CrushResult<()> {
                 "combine to streams of data into one", None)?;
             env.declare_command(
                 "seq", seq::perform, true,
-                "seq lines:integer",
-                "Return a stream of numbers",
-                None)?;
+                "seq [to] [from=value] [step=value]",
+                "Return a stream of numbers or times",
+                Some(r#"    The sequence starts at from, which defaults to zero, and every value is step larger
+    than the previous one. The step defaults to one and may be negative. The sequence ends
+    before reaching to. Without an end value, the sequence is infinite and stops once the
+    receiver stops reading, e.g. when piped into head.
+
+    The type of the values follows the arguments. If any of them is a float, so are the
+    values. For a sequence of times, from must be a time and step must be a duration.
+
+    Examples:
+
+    seq 5
+    seq from=10 to=0 step=(neg 2)
+    seq 1.0 step=0.25
+    seq from=(time:now) step=(duration:new 1 "days") | head 7"#))?;
+            env.declare_command(
+                "tee", tee::perform, true,
+                "tee name=(closure|file)...",
+                "Send a copy of the input to several closures or files",
+                Some(r#"    Each closure is called with a copy of the input stream, and each file is
+    written one line per row, like file:write does. Once all of them are done, tee
+    returns a struct containing the materialized output of every closure and the
+    written files, using the argument names as field names.
+
+    Example:
+
+    res := (ls | tee files={count} largest={sort ^size | tail 3} listing=./listing.txt)
+    res:largest"#))?;
             Ok(())
         }))?;
     root.r#use(&e);
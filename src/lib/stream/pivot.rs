@@ -0,0 +1,219 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, argument_error, mandate};
+use crate::lang::{table::Row, table::Table, value::Value, value::ValueType, argument::Argument};
+use crate::lang::stream::{empty_channel, black_hole};
+use crate::lang::table::{ColumnType, ColumnVec};
+use crate::lang::command::CrushCommand;
+use super::aggr::{Aggregation, run_aggregations};
+use std::collections::{HashMap, HashSet};
+
+struct PivotConfig {
+    key: usize,
+    value: usize,
+    aggregation: Option<Box<dyn CrushCommand + Send + Sync>>,
+}
+
+fn field(input_type: &[ColumnType], value: Value) -> CrushResult<usize> {
+    match value {
+        Value::Field(f) => input_type.find(&f),
+        _ => argument_error("Expected a field"),
+    }
+}
+
+fn parse_pivot(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<PivotConfig> {
+    let mut key = None;
+    let mut value = None;
+    let mut aggregation = None;
+    for arg in arguments {
+        match (arg.argument_type.as_deref(), arg.value) {
+            (Some("key"), v) => key = Some(field(input_type, v)?),
+            (Some("value"), v) => value = Some(field(input_type, v)?),
+            (Some("aggregation"), Value::Command(c)) => aggregation = Some(c),
+            _ => return argument_error("Expected a key field, a value field and an optional aggregation"),
+        }
+    }
+    let key = mandate(key, "Missing key column")?;
+    let value = mandate(value, "Missing value column")?;
+    if key == value {
+        return argument_error("The key and value columns must be different");
+    }
+    for (idx, t) in input_type.iter().enumerate() {
+        if idx != value && !t.cell_type.is_hashable() {
+            return argument_error(format!("Can't pivot on column {} of type {}", t.name, t.cell_type.to_string()).as_str());
+        }
+    }
+    Ok(PivotConfig { key, value, aggregation })
+}
+
+/**
+  The type of a column where all cells have the same type, otherwise any.
+*/
+fn common_type(cells: impl Iterator<Item=ValueType>) -> ValueType {
+    let mut res = None;
+    for t in cells {
+        match &res {
+            None => res = Some(t),
+            Some(r) if *r == t => {}
+            Some(_) => return ValueType::Any,
+        }
+    }
+    res.unwrap_or(ValueType::Any)
+}
+
+/**
+  Every key becomes a column, so keys must be distinct from each other and from the index
+  columns once they are turned into column names.
+*/
+fn check_names<'a>(names: &[String], index_names: impl Iterator<Item=&'a String>) -> CrushResult<()> {
+    let mut seen = index_names.collect::<HashSet<_>>();
+    for name in names {
+        if !seen.insert(name) {
+            return argument_error(format!("Pivoting would create more than one column named {}", name).as_str());
+        }
+    }
+    Ok(())
+}
+
+pub fn pivot(context: ExecutionContext) -> CrushResult<()> {
+    let mut input = mandate(context.input.recv()?.readable(), "Expected a stream")?;
+    let input_type = input.types().to_vec();
+    let cfg = parse_pivot(&input_type, context.arguments)?;
+    let index_columns = (0..input_type.len())
+        .filter(|idx| *idx != cfg.key && *idx != cfg.value)
+        .collect::<Vec<_>>();
+
+    let mut names: Vec<String> = Vec::new();
+    let mut name_index: HashMap<Value, usize> = HashMap::new();
+    let mut rows: Vec<(Vec<Value>, Vec<Vec<Value>>)> = Vec::new();
+    let mut row_index: HashMap<Vec<Value>, usize> = HashMap::new();
+
+    while let Ok(row) = input.read() {
+        let index = index_columns.iter().map(|idx| row.cells()[*idx].clone()).collect::<Vec<_>>();
+        let key = row.cells()[cfg.key].clone();
+        let column = *name_index.entry(key.clone()).or_insert_with(|| {
+            names.push(key.to_string());
+            names.len() - 1
+        });
+        let row_idx = *row_index.entry(index.clone()).or_insert_with(|| {
+            rows.push((index, Vec::new()));
+            rows.len() - 1
+        });
+        let values = &mut rows[row_idx].1;
+        values.resize(names.len(), Vec::new());
+        values[column].push(row.cells()[cfg.value].clone());
+    }
+
+    check_names(&names, index_columns.iter().map(|idx| &input_type[*idx].name))?;
+
+    let aggregations = cfg.aggregation.map(|command| vec![Aggregation { name: "aggregation".to_string(), command }]);
+    let value_type = vec![input_type[cfg.value].clone()];
+    let base_context = ExecutionContext {
+        input: empty_channel(),
+        output: black_hole(),
+        arguments: vec![],
+        env: context.env,
+        this: None,
+        printer: context.printer,
+    };
+
+    let mut output_rows = Vec::new();
+    for (index, mut values) in rows {
+        values.resize(names.len(), Vec::new());
+        let mut cells = index;
+        for (name, mut v) in names.iter().zip(values) {
+            cells.push(match (&aggregations, v.len()) {
+                (_, 0) => Value::Empty(),
+                (None, 1) => v.remove(0),
+                (None, _) => return argument_error(
+                    format!("Multiple values for column {}, use an aggregation to combine them", name).as_str()),
+                (Some(aggregations), _) => {
+                    let table = Value::Table(Table::new(
+                        value_type.clone(),
+                        v.into_iter().map(|c| Row::new(vec![c])).collect()));
+                    run_aggregations(table, aggregations, &base_context)?.remove(0)
+                }
+            });
+        }
+        output_rows.push(Row::new(cells));
+    }
+
+    let mut output_type = index_columns.iter().map(|idx| input_type[*idx].clone()).collect::<Vec<_>>();
+    for (idx, name) in names.iter().enumerate() {
+        let column = index_columns.len() + idx;
+        let cell_type = match &aggregations {
+            None => input_type[cfg.value].cell_type.clone(),
+            Some(_) => common_type(output_rows.iter()
+                .map(|r| r.cells()[column].value_type())
+                .filter(|t| *t != ValueType::Empty)),
+        };
+        output_type.push(ColumnType::new(name, cell_type));
+    }
+    let output = context.output.initialize(output_type)?;
+    for row in output_rows {
+        output.send(row)?;
+    }
+    Ok(())
+}
+
+struct UnpivotConfig {
+    columns: Vec<usize>,
+    key: String,
+    value: String,
+}
+
+fn name(value: Value) -> CrushResult<String> {
+    match value {
+        Value::Field(f) if f.len() == 1 => Ok(f[0].clone()),
+        Value::String(s) => Ok(s.to_string()),
+        _ => argument_error("Expected a column name"),
+    }
+}
+
+fn parse_unpivot(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<UnpivotConfig> {
+    let mut cfg = UnpivotConfig { columns: Vec::new(), key: "key".to_string(), value: "value".to_string() };
+    for arg in arguments {
+        match (arg.argument_type.as_deref(), arg.value) {
+            (None, v) => cfg.columns.push(field(input_type, v)?),
+            (Some("key"), v) => cfg.key = name(v)?,
+            (Some("value"), v) => cfg.value = name(v)?,
+            _ => return argument_error("Expected the fields to unpivot and optional key and value names"),
+        }
+    }
+    if cfg.columns.is_empty() {
+        return argument_error("No columns to unpivot specified");
+    }
+    Ok(cfg)
+}
+
+pub fn unpivot(context: ExecutionContext) -> CrushResult<()> {
+    let mut input = mandate(context.input.recv()?.readable(), "Expected a stream")?;
+    let input_type = input.types().to_vec();
+    let cfg = parse_unpivot(&input_type, context.arguments)?;
+
+    let mut output_type = input_type.iter()
+        .enumerate()
+        .filter(|(idx, _)| !cfg.columns.contains(idx))
+        .map(|(_, t)| t.clone())
+        .collect::<Vec<_>>();
+    output_type.push(ColumnType::new(&cfg.key, ValueType::String));
+    output_type.push(ColumnType::new(
+        &cfg.value,
+        common_type(cfg.columns.iter().map(|idx| input_type[*idx].cell_type.clone()))));
+    let output = context.output.initialize(output_type)?;
+
+    while let Ok(row) = input.read() {
+        let cells = row.into_vec();
+        let rest = cells.iter()
+            .enumerate()
+            .filter(|(idx, _)| !cfg.columns.contains(idx))
+            .map(|(_, c)| c.clone())
+            .collect::<Vec<_>>();
+        for idx in &cfg.columns {
+            let mut out = rest.clone();
+            out.push(Value::string(&input_type[*idx].name));
+            out.push(cells[*idx].clone());
+            output.send(Row::new(out))?;
+        }
+    }
+    Ok(())
+}
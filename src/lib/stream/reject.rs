@@ -0,0 +1,46 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, error, argument_error};
+use crate::lang::{table::Row, value::Value, argument::Argument};
+use crate::lang::stream::{Readable, ValueSender};
+use crate::lang::table::{ColumnType, ColumnVec};
+
+fn parse(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<Vec<bool>> {
+    let mut keep = vec![true; input_type.len()];
+    for arg in arguments {
+        match (arg.argument_type, arg.value) {
+            (None, Value::Field(f)) => keep[input_type.find(&f)?] = false,
+            (None, Value::String(s)) => keep[input_type.find_str(&s)?] = false,
+            _ => return argument_error("Expected the fields to remove"),
+        }
+    }
+    Ok(keep)
+}
+
+pub fn run(keep: Vec<bool>, input: &mut dyn Readable, sender: ValueSender) -> CrushResult<()> {
+    let output_type = input.types().iter()
+        .zip(keep.iter())
+        .filter(|(_, k)| **k)
+        .map(|(t, _)| t.clone())
+        .collect();
+    let output = sender.initialize(output_type)?;
+
+    while let Ok(row) = input.read() {
+        output.send(Row::new(
+            row.into_vec().into_iter()
+                .zip(keep.iter())
+                .filter(|(_, k)| **k)
+                .map(|(c, _)| c)
+                .collect()))?;
+    }
+    Ok(())
+}
+
+pub fn perform(context: ExecutionContext) -> CrushResult<()> {
+    match context.input.recv()?.readable() {
+        Some(mut r) => {
+            let keep = parse(r.types(), context.arguments)?;
+            run(keep, r.as_mut(), context.output)
+        }
+        None => error("Expected a stream"),
+    }
+}
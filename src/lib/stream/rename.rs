@@ -0,0 +1,42 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, error, argument_error};
+use crate::lang::{value::Value, argument::Argument};
+use crate::lang::stream::{Readable, ValueSender};
+use crate::lang::table::{ColumnType, ColumnVec};
+
+fn parse(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<Vec<ColumnType>> {
+    let mut output_type = input_type.to_vec();
+    for arg in arguments {
+        match (arg.argument_type, arg.value) {
+            (Some(old), Value::Field(new)) if new.len() == 1 => {
+                let idx = input_type.find_str(&old)?;
+                output_type[idx] = ColumnType::new(&new[0], input_type[idx].cell_type.clone());
+            }
+            _ => return argument_error("Expected arguments like old_name=^new_name"),
+        }
+    }
+    for (idx, c) in output_type.iter().enumerate() {
+        if output_type[idx + 1..].iter().any(|o| o.name == c.name) {
+            return argument_error(format!("Duplicate column name {}", c.name).as_str());
+        }
+    }
+    Ok(output_type)
+}
+
+pub fn run(output_type: Vec<ColumnType>, input: &mut dyn Readable, sender: ValueSender) -> CrushResult<()> {
+    let output = sender.initialize(output_type)?;
+    while let Ok(row) = input.read() {
+        output.send(row)?;
+    }
+    Ok(())
+}
+
+pub fn perform(context: ExecutionContext) -> CrushResult<()> {
+    match context.input.recv()?.readable() {
+        Some(mut r) => {
+            let output_type = parse(r.types(), context.arguments)?;
+            run(output_type, r.as_mut(), context.output)
+        }
+        None => error("Expected a stream"),
+    }
+}
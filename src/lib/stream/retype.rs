@@ -0,0 +1,49 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, error, argument_error};
+use crate::lang::{table::Row, value::Value, value::ValueType, argument::Argument};
+use crate::lang::stream::{Readable, ValueSender};
+use crate::lang::table::{ColumnType, ColumnVec};
+
+fn parse(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<Vec<Option<ValueType>>> {
+    let mut casts = vec![None; input_type.len()];
+    for arg in arguments {
+        match (arg.argument_type, arg.value) {
+            (Some(name), Value::Type(t)) => casts[input_type.find_str(&name)?] = Some(t),
+            _ => return argument_error("Expected arguments like column=type"),
+        }
+    }
+    Ok(casts)
+}
+
+pub fn run(casts: Vec<Option<ValueType>>, input: &mut dyn Readable, sender: ValueSender) -> CrushResult<()> {
+    let output_type = input.types().iter()
+        .zip(casts.iter())
+        .map(|(c, t)| match t {
+            Some(t) => ColumnType::new(&c.name, t.clone()),
+            None => c.clone(),
+        })
+        .collect();
+    let output = sender.initialize(output_type)?;
+
+    while let Ok(row) = input.read() {
+        output.send(Row::new(
+            row.into_vec().into_iter()
+                .zip(casts.iter())
+                .map(|(c, t)| match t {
+                    Some(t) => c.cast(t.clone()),
+                    None => Ok(c),
+                })
+                .collect::<CrushResult<Vec<_>>>()?))?;
+    }
+    Ok(())
+}
+
+pub fn perform(context: ExecutionContext) -> CrushResult<()> {
+    match context.input.recv()?.readable() {
+        Some(mut r) => {
+            let casts = parse(r.types(), context.arguments)?;
+            run(casts, r.as_mut(), context.output)
+        }
+        None => error("Expected a stream"),
+    }
+}
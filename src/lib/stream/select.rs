@@ -50,7 +50,7 @@ pub fn run(
                 first_result.append(&mut row.cells().clone());
             }
             for (location, source) in &config.columns {
-                let value = match source {
+                let (value, cell_type) = match source {
                     Source::Closure(closure) => {
                         let (sender, receiver) = channels();
                         let arguments: Vec<Argument> = row
@@ -69,18 +69,21 @@ pub fn run(
                                 printer: context.printer.clone(),
                             }
                         )?;
-                        receiver.recv()?
+                        let value = receiver.recv()?;
+                        let cell_type = value.value_type();
+                        (value, cell_type)
                     }
-                    Source::Argument(idx) => row.cells()[*idx].clone(),
+                    /* Selected columns keep their declared type, which may be wider than that of the first value. */
+                    Source::Argument(idx) => (row.cells()[*idx].clone(), input_type[*idx].cell_type.clone()),
                 };
 
                 match location {
                     Location::Append(name) => {
-                        output_type.push(ColumnType::new(name.as_ref(), value.value_type()));
+                        output_type.push(ColumnType::new(name.as_ref(), cell_type));
                         first_result.push(value);
                     }
                     Location::Replace(idx) => {
-                        output_type.replace(*idx, ColumnType::new(output_type[*idx].name.as_ref(), value.value_type()));
+                        output_type.replace(*idx, ColumnType::new(output_type[*idx].name.as_ref(), cell_type));
                         first_result[*idx] = value;
                     }
                 }
@@ -1,5 +1,5 @@
-use crate::lang::execution_context::{ExecutionContext, ArgumentVector};
-use crate::lang::errors::{CrushResult};
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, argument_error};
 use crate::{
     lang::{
         table::Row,
@@ -8,14 +8,136 @@ use crate::{
     }
 };
 use crate::lang::table::ColumnType;
+use crate::lang::stream::OutputStream;
+use chrono::{DateTime, Duration, Local};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use num_bigint::BigInt;
 
-pub fn perform(mut context: ExecutionContext) -> CrushResult<()> {
-    let c  = context.arguments.optional_integer(0)?.unwrap_or(i128::max_value());
-    let output = context.output.initialize(vec![
-        ColumnType::new("value", ValueType::Integer)])?;
+struct Config {
+    from: Option<Value>,
+    to: Option<Value>,
+    step: Option<Value>,
+}
+
+fn parse(context: ExecutionContext) -> CrushResult<(Config, OutputStream, ValueType)> {
+    let mut cfg = Config { from: None, to: None, step: None };
+    for arg in context.arguments {
+        match arg.argument_type.as_deref() {
+            None | Some("to") if cfg.to.is_none() => cfg.to = Some(arg.value),
+            Some("from") if cfg.from.is_none() => cfg.from = Some(arg.value),
+            Some("step") if cfg.step.is_none() => cfg.step = Some(arg.value),
+            _ => return argument_error("Expected at most one each of from, to and step"),
+        }
+    }
+    let cell_type = match (&cfg.from, &cfg.to, &cfg.step) {
+        (Some(Value::Time(_)), _, _) | (_, Some(Value::Time(_)), _) => ValueType::Time,
+        (Some(Value::Float(_)), _, _) | (_, Some(Value::Float(_)), _) | (_, _, Some(Value::Float(_))) => ValueType::Float,
+        _ => ValueType::Integer,
+    };
+    let output = context.output.initialize(vec![ColumnType::new("value", cell_type.clone())])?;
+    Ok((cfg, output, cell_type))
+}
 
-    for i in 0..c {
-        output.send(Row::new(vec![Value::Integer(i)]))?;
+/**
+  Send the values from the start, one step apart, until the end is reached or passed. The nth
+  value is calculated directly from the start, so that rounding errors don't add up. Integer
+  sequences are calculated with big integers, so they continue past the range of i128. Without an
+  end, values are sent until the receiving end of the output is closed, which is not an error.
+*/
+fn run<T: PartialOrd>(
+    to: Option<T>,
+    ascending: bool,
+    nth: impl Fn(i64) -> Option<T>,
+    value: impl Fn(T) -> Value,
+    output: OutputStream) -> CrushResult<()> {
+    let end = if ascending { Ordering::Less } else { Ordering::Greater };
+    for n in 0i64.. {
+        let current = match nth(n) {
+            Some(current) => current,
+            None => break,
+        };
+        if let Some(to) = &to {
+            if current.partial_cmp(to) != Some(end) {
+                break;
+            }
+        }
+        if output.send(Row::new(vec![value(current)])).is_err() {
+            break;
+        }
     }
     Ok(())
 }
+
+fn integer(value: Option<Value>, default: i128) -> CrushResult<BigInt> {
+    match value {
+        None => Ok(BigInt::from(default)),
+        Some(Value::Integer(i)) => Ok(BigInt::from(i)),
+        Some(Value::BigInteger(i)) => Ok(i),
+        Some(_) => argument_error("Expected an integer"),
+    }
+}
+
+fn float(value: Option<Value>, default: f64) -> CrushResult<f64> {
+    match value {
+        None => Ok(default),
+        Some(Value::Integer(i)) => Ok(i as f64),
+        Some(Value::Float(f)) => Ok(f),
+        Some(_) => argument_error("Expected a number"),
+    }
+}
+
+fn time(value: Option<Value>) -> CrushResult<Option<DateTime<Local>>> {
+    match value {
+        None => Ok(None),
+        Some(Value::Time(t)) => Ok(Some(t)),
+        Some(_) => argument_error("Expected a time"),
+    }
+}
+
+pub fn perform(context: ExecutionContext) -> CrushResult<()> {
+    let (cfg, output, cell_type) = parse(context)?;
+    match cell_type {
+        ValueType::Integer => {
+            let step = integer(cfg.step, 1)?;
+            if step == BigInt::from(0) {
+                return argument_error("The step can not be zero");
+            }
+            let to = match cfg.to {
+                None => None,
+                to => Some(integer(to, 0)?),
+            };
+            let from = integer(cfg.from, 0)?;
+            let ascending = step > BigInt::from(0);
+            run(to, ascending, |n| Some(&from + &step * n), Value::big_integer, output)
+        }
+        ValueType::Float => {
+            let step = float(cfg.step, 1.0)?;
+            if step == 0.0 || !step.is_finite() {
+                return argument_error("The step must be a finite, non-zero number");
+            }
+            let from = float(cfg.from, 0.0)?;
+            let to = match cfg.to {
+                None => None,
+                to => Some(float(to, 0.0)?),
+            };
+            run(to, step > 0.0, |n| Some(from + step * n as f64), Value::Float, output)
+        }
+        _ => {
+            let step = match cfg.step {
+                Some(Value::Duration(d)) => d,
+                _ => return argument_error("Time sequences need a step of type duration"),
+            };
+            if step == Duration::seconds(0) {
+                return argument_error("The step can not be zero");
+            }
+            let from = match time(cfg.from)? {
+                Some(from) => from,
+                None => return argument_error("Time sequences need a start time"),
+            };
+            let to = time(cfg.to)?;
+            run(to, step > Duration::seconds(0), |n| i32::try_from(n).ok()
+                .and_then(|n| from.checked_add_signed(step * n)), Value::Time, output)
+        }
+    }
+}
@@ -2,38 +2,246 @@ use crate::{
     lang::errors::argument_error,
     lang::stream::OutputStream,
 };
-use crate::lang::execution_context::{ExecutionContext, ArgumentVector};
+use crate::lang::execution_context::ExecutionContext;
 use crate::lang::{argument::Argument, table::Row};
-use crate::lang::errors::{CrushResult, error};
-use crate::lang::stream::Readable;
+use crate::lang::errors::{CrushResult, error, mandate};
+use crate::lang::stream::{Readable, channels, empty_channel, black_hole};
 use crate::lang::table::{ColumnType, ColumnVec};
+use crate::lang::value::{Value, ValueType};
+use crate::lang::command::CrushCommand;
+use std::cmp::Ordering;
+
+enum KeySource {
+    Column(usize),
+    Closure(Box<dyn CrushCommand + Send + Sync>),
+}
+
+struct Key {
+    source: KeySource,
+    descending: bool,
+}
+
+pub struct Config {
+    keys: Vec<Key>,
+    compare: Option<Box<dyn CrushCommand + Send + Sync>>,
+    reverse: bool,
+    natural: bool,
+}
+
+fn key(value: Value, descending: bool, types: &[ColumnType]) -> CrushResult<Key> {
+    let source = match value {
+        Value::Field(f) => {
+            let idx = types.find(&f)?;
+            let cell_type = &types[idx].cell_type;
+            if !cell_type.is_comparable() && *cell_type != ValueType::Struct {
+                return argument_error("Bad comparison key");
+            }
+            KeySource::Column(idx)
+        }
+        Value::Command(c) => KeySource::Closure(c),
+        _ => return argument_error("Expected a field or a closure as sort key"),
+    };
+    Ok(Key { source, descending })
+}
 
 fn parse(
-    mut arguments: Vec<Argument>,
+    arguments: Vec<Argument>,
+    types: &[ColumnType],
+) -> CrushResult<Config> {
+    let mut config = Config { keys: Vec::new(), compare: None, reverse: false, natural: false };
+    for arg in arguments {
+        match (arg.argument_type.as_deref(), arg.value) {
+            (None, value) => config.keys.push(key(value, false, types)?),
+            (Some("desc"), value) => config.keys.push(key(value, true, types)?),
+            (Some("compare"), Value::Command(c)) => config.compare = Some(c),
+            (Some("reverse"), Value::Bool(b)) => config.reverse = b,
+            (Some("natural"), Value::Bool(b)) => config.natural = b,
+            _ => return argument_error("Unknown sort argument"),
+        }
+    }
+    if config.keys.is_empty() && config.compare.is_none() {
+        if types.len() != 1 {
+            return argument_error("No sort key specified");
+        }
+        config.keys.push(key(Value::Field(vec![types[0].name.clone()]), false, types)?);
+    }
+    Ok(config)
+}
+
+fn call(command: &(dyn CrushCommand + Send + Sync), arguments: Vec<Argument>, context: &ExecutionContext) -> CrushResult<Value> {
+    let (sender, receiver) = channels();
+    command.invoke(context.clone().with_args(arguments, None).with_sender(sender))?;
+    mandate(receiver.recv().ok(), "Sort closure did not return a value")
+}
+
+/**
+  The values to sort a row by. Key closures are called with the cells of the row as named
+  arguments.
+*/
+fn sort_key(row: &Row, config: &Config, types: &[ColumnType], context: &ExecutionContext) -> CrushResult<Vec<Value>> {
+    config.keys.iter()
+        .map(|k| match &k.source {
+            KeySource::Column(idx) => Ok(row.cells()[*idx].clone()),
+            KeySource::Closure(c) => call(
+                c.as_ref(),
+                row.cells().iter()
+                    .zip(types.iter())
+                    .map(|(v, t)| Argument::named(&t.name, v.clone()))
+                    .collect(),
+                context),
+        })
+        .collect()
+}
+
+/**
+  Compare strings so that embedded numbers are ordered by their value, e.g. file2 sorts before
+  file10.
+*/
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().cloned(), b.peek().cloned()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x_digits = String::new();
+                while let Some(c) = a.peek().cloned().filter(char::is_ascii_digit) {
+                    x_digits.push(c);
+                    a.next();
+                }
+                let mut y_digits = String::new();
+                while let Some(c) = b.peek().cloned().filter(char::is_ascii_digit) {
+                    y_digits.push(c);
+                    b.next();
+                }
+                let x_num = x_digits.trim_start_matches('0');
+                let y_num = y_digits.trim_start_matches('0');
+                let ordering = x_num.len().cmp(&y_num.len())
+                    .then_with(|| x_num.cmp(y_num))
+                    .then_with(|| x_digits.len().cmp(&y_digits.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn compare_values(a: &Value, b: &Value, config: &Config, context: &ExecutionContext) -> CrushResult<Ordering> {
+    match (a, b) {
+        (Value::String(x), Value::String(y)) if config.natural => Ok(natural_cmp(x, y)),
+        (Value::Empty(), Value::Empty()) => Ok(Ordering::Equal),
+        (Value::Empty(), _) => Ok(Ordering::Less),
+        (_, Value::Empty()) => Ok(Ordering::Greater),
+        _ => match a.compare(b, &context.env, &context.printer)? {
+            Some(ordering) => Ok(ordering),
+            None => error(format!(
+                "Can't compare values of type {} and {}",
+                a.value_type().to_string(),
+                b.value_type().to_string()).as_str()),
+        },
+    }
+}
+
+/**
+  The comparator closure is called with the two rows as the structs a and b. It returns either
+  a boolean telling if a sorts before b, or an integer that is negative, zero or positive. It is
+  also called with the rows swapped, and contradicting answers are an error.
+*/
+fn compare_rows(
+    command: &(dyn CrushCommand + Send + Sync),
+    a: &Row,
+    b: &Row,
     types: &[ColumnType],
-) -> CrushResult<usize> {
-    arguments.check_len_range(0, 1)?;
-    if let Some(f) = arguments.optional_field(0)? {
-        Ok(types.find(&f)?)
-    } else if types.len() != 1 {
-        argument_error("No sort key specified")
-    } else {
-        Ok(0)
+    context: &ExecutionContext) -> CrushResult<Ordering> {
+    let arguments = |x: &Row, y: &Row| vec![
+        Argument::named("a", Value::Struct(x.clone().into_struct(types))),
+        Argument::named("b", Value::Struct(y.clone().into_struct(types))),
+    ];
+    let forward = call(command, arguments(a, b), context)?;
+    let backward = call(command, arguments(b, a), context)?;
+    match (forward, backward) {
+        (Value::Integer(x), Value::Integer(y)) if x.cmp(&0) == y.cmp(&0).reverse() => Ok(x.cmp(&0)),
+        (Value::Bool(true), Value::Bool(false)) => Ok(Ordering::Less),
+        (Value::Bool(false), Value::Bool(true)) => Ok(Ordering::Greater),
+        (Value::Bool(false), Value::Bool(false)) => Ok(Ordering::Equal),
+        (Value::Integer(_), Value::Integer(_)) | (Value::Bool(_), Value::Bool(_)) =>
+            error("The comparator gave inconsistent results when called with the rows swapped"),
+        _ => error("Expected the comparator to return a boolean or an integer"),
     }
 }
 
-pub fn run(idx: usize, input: &mut dyn Readable, output: OutputStream) -> CrushResult<()> {
-    let mut res: Vec<Row> = Vec::new();
+fn compare(
+    a: &(Vec<Value>, Row),
+    b: &(Vec<Value>, Row),
+    config: &Config,
+    types: &[ColumnType],
+    context: &ExecutionContext) -> CrushResult<Ordering> {
+    for ((x, y), k) in a.0.iter().zip(b.0.iter()).zip(config.keys.iter()) {
+        let ordering = compare_values(x, y, config, context)?;
+        if ordering != Ordering::Equal {
+            return Ok(if k.descending { ordering.reverse() } else { ordering });
+        }
+    }
+    match &config.compare {
+        Some(c) => compare_rows(c.as_ref(), &a.1, &b.1, types, context),
+        None => Ok(Ordering::Equal),
+    }
+}
+
+/**
+  A stable merge sort with a comparison that may fail. Sorting stops at the first error, and
+  comparisons that are not consistent can't cause a panic.
+*/
+fn merge_sort<T>(
+    mut items: Vec<T>,
+    cmp: &mut impl FnMut(&T, &T) -> CrushResult<Ordering>) -> CrushResult<Vec<T>> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, cmp)?;
+    let right = merge_sort(right, cmp)?;
+
+    let mut res = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        let next = if cmp(r, l)? == Ordering::Less { right.next() } else { left.next() };
+        res.extend(next);
+    }
+    res.extend(left);
+    res.extend(right);
+    Ok(res)
+}
+
+pub fn run(
+    config: Config,
+    input: &mut dyn Readable,
+    output: OutputStream,
+    context: &ExecutionContext,
+) -> CrushResult<()> {
+    let types = input.types().to_vec();
+    let mut res = Vec::new();
     while let Ok(row) = input.read() {
-        res.push(row);
+        res.push((sort_key(&row, &config, &types, context)?, row));
     }
 
-    res.sort_by(|a, b|
-        a.cells()[idx]
-            .partial_cmp(&b.cells()[idx])
-            .expect("OH NO!"));
+    let res = merge_sort(res, &mut |a, b| {
+        let ordering = compare(a, b, &config, &types, context)?;
+        Ok(if config.reverse { ordering.reverse() } else { ordering })
+    })?;
 
-    for row in res {
+    for (_, row) in res {
         output.send(row)?;
     }
 
@@ -43,14 +251,51 @@ pub fn run(idx: usize, input: &mut dyn Readable, output: OutputStream) -> CrushR
 pub fn perform(context: ExecutionContext) -> CrushResult<()> {
     match context.input.recv()?.readable() {
         Some(mut input) => {
+            let config = parse(context.arguments, input.types())?;
             let output = context.output.initialize(input.types().to_vec())?;
-            let idx = parse(context.arguments, input.types())?;
-            if input.types()[idx].cell_type.is_comparable() {
-                run(idx, input.as_mut(), output)
-            } else {
-                argument_error("Bad comparison key")
-            }
+            let base_context = ExecutionContext {
+                input: empty_channel(),
+                output: black_hole(),
+                arguments: vec![],
+                env: context.env,
+                this: None,
+                printer: context.printer,
+            };
+            run(config, input.as_mut(), output, &base_context)
         }
         None => error("Expected a stream"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_sort_is_stable() {
+        let items = vec![(2, 'a'), (1, 'b'), (2, 'c'), (0, 'd'), (1, 'e')];
+        let sorted = merge_sort(items, &mut |a, b| Ok(a.0.cmp(&b.0))).unwrap();
+        assert_eq!(sorted, vec![(0, 'd'), (1, 'b'), (1, 'e'), (2, 'a'), (2, 'c')]);
+    }
+
+    #[test]
+    fn merge_sort_stops_at_first_error() {
+        let mut calls = 0;
+        let res = merge_sort(vec![3, 2, 1, 0], &mut |a: &i32, b: &i32| {
+            calls += 1;
+            if calls == 1 { error("Bad comparison") } else { Ok(a.cmp(b)) }
+        });
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file10", "file2"), Ordering::Greater);
+        assert_eq!(natural_cmp("a", "b"), Ordering::Less);
+        assert_eq!(natural_cmp("x007", "x7"), Ordering::Greater);
+        assert_eq!(natural_cmp("v1.10", "v1.9"), Ordering::Greater);
+        assert_eq!(natural_cmp("abc", "abc"), Ordering::Equal);
+    }
+}
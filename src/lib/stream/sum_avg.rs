@@ -1,5 +1,5 @@
 use crate::lang::execution_context::ExecutionContext;
-use crate::lang::errors::{CrushResult, error, argument_error};
+use crate::lang::errors::{CrushResult, error, argument_error, mandate};
 use crate::{
     lang::{
         value::ValueType,
@@ -7,10 +7,14 @@ use crate::{
     }
 };
 use crate::lang::{table::ColumnType, argument::Argument};
-use crate::lang::stream::Readable;
 use crate::lang::table::ColumnVec;
 use chrono::Duration;
 use float_ord::FloatOrd;
+use num_bigint::BigInt;
+use bigdecimal::BigDecimal;
+use crate::lib::types::decimal::default_divide;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
 
 pub fn parse(input_type: &[ColumnType], arguments: &[Argument]) -> CrushResult<usize> {
     match arguments.len() {
@@ -37,137 +41,221 @@ pub fn parse(input_type: &[ColumnType], arguments: &[Argument]) -> CrushResult<u
     }
 }
 
-macro_rules! sum_function {
-    ($name:ident, $var_type:ident, $var_initializer:expr, $value_type:ident) => {
-fn $name(mut s: Box<dyn Readable>, column: usize) -> CrushResult<Value> {
-    let mut res: $var_type = $var_initializer;
-    while let Ok(row) = s.read() {
-match row.cells()[column] {
-                Value::$value_type(i) => res = res + i,
-                _ => return error("Invalid cell value")
-            }
-    }
-    Ok(Value::$value_type(res))
-}
-    }
+/**
+  The aggregations that are computed one value at a time, like sum and avg, and can therefore be
+  used while grouping or windowing without materializing any rows.
+*/
+#[derive(Clone, Copy, PartialEq)]
+pub enum Function {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
 }
 
-sum_function!(sum_int, i128, 0, Integer);
-sum_function!(sum_float, f64, 0.0, Float);
-sum_function!(sum_duration, Duration, Duration::seconds(0), Duration);
+impl Function {
+    pub fn parse(name: &str) -> Option<Function> {
+        match name {
+            "count" => Some(Function::Count),
+            "sum" => Some(Function::Sum),
+            "avg" => Some(Function::Avg),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            _ => None,
+        }
+    }
 
-pub fn sum(context: ExecutionContext) -> CrushResult<()> {
-    match context.input.recv()?.readable() {
-        Some(input) => {
-            let column = parse(input.types(), &context.arguments)?;
-            match &input.types()[column].cell_type {
-                ValueType::Integer => context.output.send(sum_int(input, column)?),
-                ValueType::Float => context.output.send(sum_float(input, column)?),
-                ValueType::Duration => context.output.send(sum_duration(input, column)?),
-                t => argument_error(format!("Can't calculate sum of elements of type {}", t.to_string()).as_str())
-            }
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Count => "count",
+            Function::Sum => "sum",
+            Function::Avg => "avg",
+            Function::Min => "min",
+            Function::Max => "max",
         }
-        _ => error("Expected a stream"),
     }
-}
 
-macro_rules! avg_function {
-    ($name:ident, $var_type:ident, $var_initializer:expr, $value_type:ident, $count_type:ident) => {
-fn $name(mut s: Box<dyn Readable>, column: usize) -> CrushResult<Value> {
-    let mut res: $var_type = $var_initializer;
-    let mut count: i128 = 0;
-    loop {
-        match s.read() {
-            Ok(row) => {
-                count += 1;
-                match row.cells()[column] {
-                    Value::$value_type(i) => res = res + i,
-                    _ => return error("Invalid cell value")
-                }
-            }
-            Err(_) => break,
+    /**
+      Check that the function can be applied to values of the specified type.
+    */
+    pub fn check(self, cell_type: &ValueType) -> CrushResult<()> {
+        let supported = match self {
+            Function::Count => true,
+            Function::Sum | Function::Avg => matches!(
+                cell_type,
+                ValueType::Integer | ValueType::Float | ValueType::Decimal | ValueType::Duration),
+            Function::Min | Function::Max => matches!(
+                cell_type,
+                ValueType::Integer | ValueType::Float | ValueType::Duration | ValueType::Time),
+        };
+        if supported {
+            Ok(())
+        } else {
+            argument_error(format!(
+                "Can't calculate {} of elements of type {}", self.name(), cell_type.to_string()).as_str())
         }
     }
-    Ok(Value::$value_type(res / (count as $count_type)))
 }
-    }
+
+/**
+  Add two numbers of the same type. Integers are promoted to arbitrary precision on overflow.
+*/
+pub fn add(a: &Value, b: &Value) -> CrushResult<Value> {
+    Ok(match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => match x.checked_add(*y) {
+            Some(r) => Value::Integer(r),
+            None => Value::big_integer(BigInt::from(*x) + BigInt::from(*y)),
+        },
+        (Value::Integer(_), Value::BigInteger(_)) | (Value::BigInteger(_), Value::Integer(_)) |
+        (Value::BigInteger(_), Value::BigInteger(_)) => Value::big_integer(big(a)? + big(b)?),
+        (Value::Float(x), Value::Float(y)) => Value::Float(x + y),
+        (Value::Decimal(x), Value::Decimal(y)) => Value::Decimal(x + y),
+        (Value::Duration(x), Value::Duration(y)) => Value::Duration(*x + *y),
+        _ => return error("Invalid cell value"),
+    })
 }
 
-avg_function!(avg_int, i128, 0, Integer, i128);
-avg_function!(avg_float, f64, 0.0, Float, f64);
-avg_function!(avg_duration, Duration, Duration::seconds(0), Duration, i32);
+/**
+  Subtract two numbers of the same type, with the same promotion as add.
+*/
+pub fn sub(a: &Value, b: &Value) -> CrushResult<Value> {
+    Ok(match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => match x.checked_sub(*y) {
+            Some(r) => Value::Integer(r),
+            None => Value::big_integer(BigInt::from(*x) - BigInt::from(*y)),
+        },
+        (Value::Integer(_), Value::BigInteger(_)) | (Value::BigInteger(_), Value::Integer(_)) |
+        (Value::BigInteger(_), Value::BigInteger(_)) => Value::big_integer(big(a)? - big(b)?),
+        (Value::Float(x), Value::Float(y)) => Value::Float(x - y),
+        (Value::Decimal(x), Value::Decimal(y)) => Value::Decimal(x - y),
+        (Value::Duration(x), Value::Duration(y)) => Value::Duration(*x - *y),
+        _ => return error("Invalid cell value"),
+    })
+}
 
-pub fn avg(context: ExecutionContext) -> CrushResult<()> {
-    match context.input.recv()?.readable() {
-        Some(input) => {
-            let column = parse(input.types(), &context.arguments)?;
-            match &input.types()[column].cell_type {
-                ValueType::Integer => context.output.send(avg_int(input, column)?),
-                ValueType::Float => context.output.send(avg_float(input, column)?),
-                ValueType::Duration => context.output.send(avg_duration(input, column)?),
-                t => argument_error(format!("Can't calculate average of elements of type {}", t.to_string()).as_str())
-            }
-        }
-        _ => error("Expected a stream"),
+fn big(value: &Value) -> CrushResult<BigInt> {
+    match value {
+        Value::Integer(i) => Ok(BigInt::from(*i)),
+        Value::BigInteger(i) => Ok(i.clone()),
+        _ => error("Invalid cell value, expected an integer"),
     }
 }
 
-macro_rules! aggr_function {
-    ($name:ident, $value_type:ident, $op:expr) => {
-fn $name(mut s: Box<dyn Readable>, column: usize) -> CrushResult<Value> {
-    let mut res = match s.read()?.cells()[column] {
-            Value::$value_type(i) => i,
-            _ => return error("Invalid cell value, expected an integer")
-    };
-    while let Ok(row) = s.read() {
-        match row.cells()[column] {
-            Value::$value_type(i) => res = $op(res, i),
-            _ => return error("Invalid cell value, expected an integer")
-        }
+/**
+  Divide a sum by the number of values it is made of.
+*/
+pub fn divide(total: &Value, count: i128) -> CrushResult<Value> {
+    if count == 0 {
+        return error("Can't calculate the average of no values");
     }
-    Ok(Value::$value_type(res))
+    Ok(match total {
+        Value::Integer(_) | Value::BigInteger(_) => Value::big_integer(big(total)? / BigInt::from(count)),
+        Value::Float(f) => Value::Float(f / count as f64),
+        Value::Decimal(d) => Value::Decimal(default_divide(d.clone(), BigDecimal::new(BigInt::from(count), 0))?),
+        Value::Duration(d) => Value::Duration(*d / mandate(i32::try_from(count).ok(), "Too many values")?),
+        _ => return error("Invalid cell value"),
+    })
 }
+
+/**
+  Compare two values of the same type. Integers may be stored as either small or big integers,
+  so they are compared as values.
+*/
+pub fn compare(a: &Value, b: &Value) -> CrushResult<Ordering> {
+    match (a, b) {
+        (Value::Float(x), Value::Float(y)) => Ok(FloatOrd(*x).cmp(&FloatOrd(*y))),
+        (Value::Integer(_), Value::Integer(_)) | (Value::Integer(_), Value::BigInteger(_)) |
+        (Value::BigInteger(_), Value::Integer(_)) | (Value::BigInteger(_), Value::BigInteger(_)) |
+        (Value::Duration(_), Value::Duration(_)) | (Value::Time(_), Value::Time(_)) =>
+            mandate(a.partial_cmp(b), "Invalid cell value"),
+        _ => error("Invalid cell value"),
     }
 }
 
-aggr_function!(min_int, Integer, |a, b| std::cmp::min(a,b));
-aggr_function!(min_float, Float, |a, b| std::cmp::min(FloatOrd(a),FloatOrd(b)).0);
-aggr_function!(min_duration, Duration, |a, b| std::cmp::min(a,b));
-aggr_function!(min_time, Time, |a, b| std::cmp::min(a,b));
+/**
+  The running state of a function. Empty cells are missing values, which are skipped by
+  everything but count.
+*/
+pub struct Accumulator {
+    function: Function,
+    cell_type: ValueType,
+    count: i128,
+    value: Option<Value>,
+}
 
-aggr_function!(max_int, Integer, |a, b| std::cmp::max(a,b));
-aggr_function!(max_float, Float, |a, b| std::cmp::max(FloatOrd(a),FloatOrd(b)).0);
-aggr_function!(max_duration, Duration, |a, b| std::cmp::max(a,b));
-aggr_function!(max_time, Time, |a, b| std::cmp::max(a,b));
+impl Accumulator {
+    pub fn new(function: Function, cell_type: &ValueType) -> CrushResult<Accumulator> {
+        function.check(cell_type)?;
+        Ok(Accumulator { function, cell_type: cell_type.clone(), count: 0, value: None })
+    }
 
-pub fn min(context: ExecutionContext) -> CrushResult<()> {
-    match context.input.recv()?.readable() {
-        Some(input) => {
-            let column = parse(input.types(), &context.arguments)?;
-            match &input.types()[column].cell_type {
-                ValueType::Integer => context.output.send(min_int(input, column)?),
-                ValueType::Float => context.output.send(min_float(input, column)?),
-                ValueType::Duration => context.output.send(min_duration(input, column)?),
-                ValueType::Time => context.output.send(min_time(input, column)?),
-                t => argument_error(format!("Can't pick min of elements of type {}", t.to_string()).as_str())
+    pub fn add(&mut self, value: &Value) -> CrushResult<()> {
+        if matches!(value, Value::Empty()) && self.function != Function::Count {
+            return Ok(());
+        }
+        self.count += 1;
+        self.value = match (self.function, self.value.take()) {
+            (Function::Count, _) => None,
+            (_, None) => {
+                if !self.cell_type.is(value) {
+                    return error("Invalid cell value");
+                }
+                Some(value.clone())
             }
+            (Function::Sum, Some(total)) | (Function::Avg, Some(total)) => Some(add(&total, value)?),
+            (Function::Min, Some(current)) =>
+                Some(if compare(value, &current)? == Ordering::Less { value.clone() } else { current }),
+            (Function::Max, Some(current)) =>
+                Some(if compare(value, &current)? == Ordering::Greater { value.clone() } else { current }),
+        };
+        Ok(())
+    }
+
+    pub fn value(self) -> CrushResult<Value> {
+        match (self.function, self.value) {
+            (Function::Count, _) => Ok(Value::Integer(self.count)),
+            (Function::Sum, Some(total)) => Ok(total),
+            (Function::Sum, None) => Ok(match self.cell_type {
+                ValueType::Float => Value::Float(0.0),
+                ValueType::Decimal => Value::Decimal(BigDecimal::from(0)),
+                ValueType::Duration => Value::Duration(Duration::seconds(0)),
+                _ => Value::Integer(0),
+            }),
+            (Function::Avg, total) =>
+                divide(&mandate(total, "Can't calculate the average of no values")?, self.count),
+            (function, None) => error(format!("Can't calculate {} of no values", function.name()).as_str()),
+            (_, Some(value)) => Ok(value),
         }
-        _ => error("Expected a stream"),
     }
 }
 
-pub fn max(context: ExecutionContext) -> CrushResult<()> {
+fn aggregate(context: ExecutionContext, function: Function) -> CrushResult<()> {
     match context.input.recv()?.readable() {
-        Some(input) => {
+        Some(mut input) => {
             let column = parse(input.types(), &context.arguments)?;
-            match &input.types()[column].cell_type {
-                ValueType::Integer => context.output.send(max_int(input, column)?),
-                ValueType::Float => context.output.send(max_float(input, column)?),
-                ValueType::Duration => context.output.send(max_duration(input, column)?),
-                ValueType::Time => context.output.send(max_time(input, column)?),
-                t => argument_error(format!("Can't pick max of elements of type {}", t.to_string()).as_str())
+            let mut accumulator = Accumulator::new(function, &input.types()[column].cell_type)?;
+            while let Ok(row) = input.read() {
+                accumulator.add(&row.cells()[column])?;
             }
+            context.output.send(accumulator.value()?)
         }
         _ => error("Expected a stream"),
     }
 }
+
+pub fn sum(context: ExecutionContext) -> CrushResult<()> {
+    aggregate(context, Function::Sum)
+}
+
+pub fn avg(context: ExecutionContext) -> CrushResult<()> {
+    aggregate(context, Function::Avg)
+}
+
+pub fn min(context: ExecutionContext) -> CrushResult<()> {
+    aggregate(context, Function::Min)
+}
+
+pub fn max(context: ExecutionContext) -> CrushResult<()> {
+    aggregate(context, Function::Max)
+}
@@ -0,0 +1,73 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, argument_error, mandate};
+use crate::lang::{argument::Argument, value::Value, r#struct::Struct};
+use crate::lang::stream::{empty_channel, black_hole};
+use crate::lang::command::CrushCommand;
+use crate::lib::types::file::replace;
+use super::aggr::{Consumer, Fanout, call};
+use std::path::PathBuf;
+
+enum Sink {
+    Closure(Box<dyn CrushCommand + Send + Sync>),
+    File(PathBuf),
+}
+
+fn parse(arguments: Vec<Argument>) -> CrushResult<Vec<(String, Sink)>> {
+    let mut res: Vec<(String, Sink)> = Vec::new();
+    for arg in arguments {
+        let name = mandate(arg.argument_type, "Expected named sinks, e.g. total={count}")?;
+        if res.iter().any(|(n, _)| *n == name) {
+            return argument_error(format!("Sink {} given more than once", name).as_str());
+        }
+        let sink = match arg.value {
+            Value::Command(c) => Sink::Closure(c),
+            Value::File(f) => Sink::File(f),
+            _ => return argument_error("Expected closures or files to send the input to"),
+        };
+        res.push((name, sink));
+    }
+    if res.is_empty() {
+        return argument_error("No sinks specified");
+    }
+    Ok(res)
+}
+
+/**
+  The output of a closure is materialized, so that the sink can finish without anyone reading
+  its output. Writing to a file returns the file.
+*/
+fn consumer(sink: Sink, context: ExecutionContext) -> Consumer {
+    match sink {
+        Sink::Closure(command) => Box::new(move |input| mandate(
+            call(command.as_ref(), Value::TableStream(input), context)?,
+            "Sink did not return a value")),
+        Sink::File(file) => Box::new(move |input| {
+            replace(&file, Value::TableStream(input))?;
+            Ok(Value::File(file))
+        }),
+    }
+}
+
+pub fn perform(context: ExecutionContext) -> CrushResult<()> {
+    let mut input = mandate(context.input.recv()?.readable(), "Expected a stream")?;
+    let base_context = ExecutionContext {
+        input: empty_channel(),
+        output: black_hole(),
+        arguments: vec![],
+        env: context.env.clone(),
+        this: None,
+        printer: context.printer.clone(),
+    };
+    let consumers = parse(context.arguments)?
+        .into_iter()
+        .map(|(name, sink)| (name, consumer(sink, base_context.clone())))
+        .collect();
+
+    let mut fanout = Fanout::start(input.types(), consumers)?;
+    while let Ok(row) = input.read() {
+        if !fanout.send(&row) {
+            break;
+        }
+    }
+    context.output.send(Value::Struct(Struct::new(fanout.finish()?, None)))
+}
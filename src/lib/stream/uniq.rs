@@ -7,6 +7,7 @@ use crate::lang::errors::{CrushResult, error};
 use crate::lang::stream::{Readable, OutputStream};
 use crate::lang::table::ColumnVec;
 use crate::lang::printer::Printer;
+use crate::lang::scope::Scope;
 
 pub fn parse(input_type: &[ColumnType], mut arguments: Vec<Argument>) -> CrushResult<Option<usize>> {
     arguments.check_len_range(0, 1)?;
@@ -17,12 +18,60 @@ pub fn parse(input_type: &[ColumnType], mut arguments: Vec<Argument>) -> CrushRe
     }
 }
 
+fn contains(seen: &[Vec<Value>], needle: &[Value], env: &Scope, printer: &Printer) -> CrushResult<bool> {
+    for candidate in seen {
+        let mut equal = true;
+        for (a, b) in candidate.iter().zip(needle.iter()) {
+            if !a.equals(b, env, printer)? {
+                equal = false;
+                break;
+            }
+        }
+        if equal {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/**
+  Values that can not be hashed, like structs with an `__eq__` method, are compared one by one.
+*/
+fn run_unhashable(
+    idx: Option<usize>,
+    input: &mut dyn Readable,
+    output: OutputStream,
+    env: &Scope,
+    printer: &Printer,
+) -> CrushResult<()> {
+    let mut seen: Vec<Vec<Value>> = Vec::new();
+    while let Ok(row) = input.read() {
+        let key = match idx {
+            None => row.cells().clone(),
+            Some(idx) => vec![row.cells()[idx].clone()],
+        };
+        if !contains(&seen, &key, env, printer)? {
+            seen.push(key);
+            printer.handle_error(output.send(row));
+        }
+    }
+    Ok(())
+}
+
 pub fn run(
     idx: Option<usize>,
     input: &mut dyn Readable,
     output: OutputStream,
+    env: &Scope,
     printer: &Printer,
 ) -> CrushResult<()> {
+    let hashable = match idx {
+        None => input.types().iter().all(|t| t.cell_type.is_hashable()),
+        Some(idx) => input.types()[idx].cell_type.is_hashable(),
+    };
+    if !hashable {
+        return run_unhashable(idx, input, output, env, printer);
+    }
     match idx {
         None => {
             let mut seen: HashSet<Row> = HashSet::new();
@@ -51,7 +100,7 @@ pub fn uniq(context: ExecutionContext) -> CrushResult<()> {
         Some(mut input) => {
             let idx = parse(input.types(), context.arguments)?;
             let output = context.output.initialize(input.types().to_vec())?;
-            run(idx, input.as_mut(), output, &context.printer)
+            run(idx, input.as_mut(), output, &context.env, &context.printer)
         }
         _ => error("Expected input to be a stream"),
     }
@@ -0,0 +1,357 @@
+use crate::lang::execution_context::ExecutionContext;
+use crate::lang::errors::{CrushResult, error, argument_error, mandate};
+use crate::lang::{table::Row, value::Value, value::ValueType, argument::Argument};
+use crate::lang::stream::{Readable, ValueSender};
+use crate::lang::table::{ColumnType, ColumnVec};
+use chrono::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::cmp::Ordering;
+use num_traits::ToPrimitive;
+use super::sum_avg::{Accumulator, Function, add, sub};
+
+fn function_type(function: Function, input: &ValueType) -> ValueType {
+    match function {
+        Function::Sum | Function::Min | Function::Max => input.clone(),
+        Function::Avg => ValueType::Float,
+        Function::Count => ValueType::Integer,
+    }
+}
+
+/**
+  The arguments of window.
+*/
+struct Config {
+    column: usize,
+    partition: Option<usize>,
+    name: Option<String>,
+    rows: Option<usize>,
+    span: Option<(Duration, usize)>,
+    function: Function,
+}
+
+fn rows(value: i128) -> CrushResult<usize> {
+    if value > 0 {
+        Ok(value as usize)
+    } else {
+        argument_error("The number of rows must be positive")
+    }
+}
+
+fn parse(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<Config> {
+    let mut column = None;
+    let mut partition = None;
+    let mut name = None;
+    let mut row_count = None;
+    let mut span = None;
+    let mut time = None;
+    let mut function = Function::Sum;
+    for arg in arguments {
+        match (arg.argument_type.as_deref(), arg.value) {
+            (None, Value::Field(f)) => column = Some(input_type.find(&f)?),
+            (Some("partition"), Value::Field(f)) => partition = Some(partition_column(input_type, &f)?),
+            (Some("name"), Value::String(s)) => name = Some(s.to_string()),
+            (Some("rows"), Value::Integer(i)) => row_count = Some(rows(i)?),
+            (Some("span"), Value::Duration(d)) => span = Some(d),
+            (Some("time"), Value::Field(f)) => time = Some(input_type.find(&f)?),
+            (Some("function"), Value::String(s)) => function = match Function::parse(&s) {
+                Some(function) => function,
+                None => return argument_error("Unknown window function, expected one of sum, avg, min, max and count"),
+            },
+            _ => return argument_error("Unexpected argument"),
+        }
+    }
+    let column = mandate(column, "Missing column")?;
+    let span = match (span, time) {
+        (Some(span), Some(time)) if input_type[time].cell_type == ValueType::Time => Some((span, time)),
+        (Some(_), Some(_)) => return argument_error("The time column must be of type time"),
+        (None, None) => None,
+        _ => return argument_error("A time span needs both the span and the time column"),
+    };
+    Ok(Config { column, partition, name, rows: row_count, span, function })
+}
+
+/**
+  The arguments of lag and lead.
+*/
+struct OffsetConfig {
+    column: usize,
+    partition: Option<usize>,
+    name: Option<String>,
+    rows: usize,
+}
+
+fn parse_offset(input_type: &[ColumnType], arguments: Vec<Argument>) -> CrushResult<OffsetConfig> {
+    let mut column = None;
+    let mut partition = None;
+    let mut name = None;
+    let mut row_count = 1;
+    for arg in arguments {
+        match (arg.argument_type.as_deref(), arg.value) {
+            (None, Value::Field(f)) => column = Some(input_type.find(&f)?),
+            (Some("partition"), Value::Field(f)) => partition = Some(partition_column(input_type, &f)?),
+            (Some("name"), Value::String(s)) => name = Some(s.to_string()),
+            (Some("rows"), Value::Integer(i)) => row_count = rows(i)?,
+            _ => return argument_error("Expected a column and optional rows, partition and name"),
+        }
+    }
+    let column = mandate(column, "Missing column")?;
+    Ok(OffsetConfig { column, partition, name, rows: row_count })
+}
+
+fn partition_column(input_type: &[ColumnType], field: &[String]) -> CrushResult<usize> {
+    let idx = input_type.find(field)?;
+    if !input_type[idx].cell_type.is_hashable() {
+        return argument_error("Can't partition on this column type");
+    }
+    Ok(idx)
+}
+
+fn partition_key(row: &Row, partition: Option<usize>) -> Vec<Value> {
+    partition.iter().map(|p| row.cells()[*p].clone()).collect()
+}
+
+/**
+  A running or rolling window. Sums and counts are updated as rows enter and leave the window.
+  For min and max, the candidates are kept in a queue ordered from the current extreme, so that
+  older values that can never become the extreme are dropped. Values are only kept for bounded
+  windows, where they must be evicted again. Empty cells count as rows but not as values.
+*/
+struct Window {
+    bounded: bool,
+    values: VecDeque<(u64, Option<Value>, Value)>,
+    extremes: VecDeque<(u64, Value)>,
+    next: u64,
+    count: usize,
+    value_count: usize,
+    sum: Option<Value>,
+}
+
+impl Window {
+    fn new(cfg: &Config) -> Window {
+        Window {
+            bounded: cfg.rows.is_some() || cfg.span.is_some(),
+            values: VecDeque::new(),
+            extremes: VecDeque::new(),
+            next: 0,
+            count: 0,
+            value_count: 0,
+            sum: None,
+        }
+    }
+
+    fn evict(&mut self, function: Function) -> CrushResult<()> {
+        if let Some((seq, _, value)) = self.values.pop_front() {
+            self.count -= 1;
+            if matches!(value, Value::Empty()) {
+                return Ok(());
+            }
+            self.value_count -= 1;
+            match function {
+                Function::Sum | Function::Avg => {
+                    self.sum = match (self.value_count, self.sum.take()) {
+                        (0, _) | (_, None) => None,
+                        (_, Some(sum)) => Some(sub(&sum, &value)?),
+                    }
+                }
+                Function::Min | Function::Max => {
+                    if self.extremes.front().map(|(s, _)| *s) == Some(seq) {
+                        self.extremes.pop_front();
+                    }
+                }
+                Function::Count => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, time: Option<Value>, value: Value, cfg: &Config) -> CrushResult<()> {
+        if let (Some((span, _)), Some(Value::Time(now))) = (&cfg.span, &time) {
+            while let Some((_, Some(Value::Time(t)), _)) = self.values.front() {
+                if *now - *t < *span {
+                    break;
+                }
+                self.evict(cfg.function)?;
+            }
+        }
+
+        let seq = self.next;
+        self.next += 1;
+        self.count += 1;
+        if !matches!(value, Value::Empty()) {
+            self.value_count += 1;
+            match cfg.function {
+                Function::Sum | Function::Avg => {
+                    self.sum = Some(match self.sum.take() {
+                        Some(sum) => add(&sum, &value)?,
+                        None => value.clone(),
+                    })
+                }
+                Function::Min | Function::Max => {
+                    let replaced = if cfg.function == Function::Min { Ordering::Less } else { Ordering::Greater };
+                    while let Some((_, last)) = self.extremes.back() {
+                        match value.partial_cmp(last) {
+                            Some(o) if o == replaced => {
+                                self.extremes.pop_back();
+                            }
+                            Some(_) => break,
+                            None => return error("Values in the window can't be compared"),
+                        }
+                    }
+                    self.extremes.push_back((seq, value.clone()));
+                    if !self.bounded {
+                        self.extremes.truncate(1);
+                    }
+                }
+                Function::Count => {}
+            }
+        }
+        if self.bounded {
+            self.values.push_back((seq, time, value));
+        }
+
+        if let Some(rows) = cfg.rows {
+            while self.count > rows {
+                self.evict(cfg.function)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn compute(&self, function: Function, cell_type: &ValueType) -> CrushResult<Value> {
+        match (function, &self.sum) {
+            (Function::Count, _) => Ok(Value::Integer(self.count as i128)),
+            (Function::Min, _) | (Function::Max, _) =>
+                Ok(self.extremes.front().map(|(_, v)| v.clone()).unwrap_or_else(Value::Empty)),
+            (Function::Sum, Some(sum)) => Ok(sum.clone()),
+            (Function::Sum, None) => Accumulator::new(Function::Sum, cell_type)?.value(),
+            (Function::Avg, None) => Ok(Value::Empty()),
+            (Function::Avg, Some(Value::Integer(i))) => Ok(Value::Float(*i as f64 / self.value_count as f64)),
+            (Function::Avg, Some(Value::BigInteger(i))) =>
+                Ok(Value::Float(i.to_f64().unwrap_or(f64::NAN) / self.value_count as f64)),
+            (Function::Avg, Some(Value::Float(f))) => Ok(Value::Float(f / self.value_count as f64)),
+            _ => error("Can't compute this window function for this column type"),
+        }
+    }
+}
+
+fn output_type(input_type: &[ColumnType], name: String, cell_type: ValueType) -> Vec<ColumnType> {
+    let mut res = input_type.to_vec();
+    res.push(ColumnType::new(&name, cell_type));
+    res
+}
+
+fn run_window(cfg: Config, input: &mut dyn Readable, sender: ValueSender) -> CrushResult<()> {
+    let input_type = input.types().to_vec();
+    let column = &input_type[cfg.column];
+    match (cfg.function, &column.cell_type) {
+        (Function::Count, _) | (Function::Min, _) | (Function::Max, _) => {}
+        (Function::Sum, ValueType::Integer) | (Function::Sum, ValueType::Float) | (Function::Sum, ValueType::Duration) => {}
+        (Function::Avg, ValueType::Integer) | (Function::Avg, ValueType::Float) => {}
+        (f, t) => return argument_error(
+            format!("Can't calculate {} of elements of type {}", f.name(), t.to_string()).as_str()),
+    }
+    let name = cfg.name.clone().unwrap_or_else(|| format!("{}_{}", column.name, cfg.function.name()));
+    let output = sender.initialize(output_type(&input_type, name, function_type(cfg.function, &column.cell_type)))?;
+
+    let mut windows: HashMap<Vec<Value>, Window> = HashMap::new();
+    while let Ok(mut row) = input.read() {
+        let window = windows.entry(partition_key(&row, cfg.partition)).or_insert_with(|| Window::new(&cfg));
+        window.push(
+            cfg.span.map(|(_, time)| row.cells()[time].clone()),
+            row.cells()[cfg.column].clone(),
+            &cfg)?;
+        row.push(window.compute(cfg.function, &input_type[cfg.column].cell_type)?);
+        output.send(row)?;
+    }
+    Ok(())
+}
+
+fn run_lag(cfg: OffsetConfig, input: &mut dyn Readable, sender: ValueSender) -> CrushResult<()> {
+    let input_type = input.types().to_vec();
+    let n = cfg.rows;
+    let name = cfg.name.clone().unwrap_or_else(|| format!("{}_lag", input_type[cfg.column].name));
+    let output = sender.initialize(output_type(&input_type, name, ValueType::Any))?;
+
+    let mut previous: HashMap<Vec<Value>, VecDeque<Value>> = HashMap::new();
+    while let Ok(mut row) = input.read() {
+        let values = previous.entry(partition_key(&row, cfg.partition)).or_default();
+        let value = row.cells()[cfg.column].clone();
+        row.push(if values.len() == n { values[0].clone() } else { Value::Empty() });
+        values.push_back(value);
+        if values.len() > n {
+            values.pop_front();
+        }
+        output.send(row)?;
+    }
+    Ok(())
+}
+
+/**
+  Rows are passed on in input order, so a row is held back until the row n positions later in
+  the same partition has been seen.
+*/
+fn run_lead(cfg: OffsetConfig, input: &mut dyn Readable, sender: ValueSender) -> CrushResult<()> {
+    let input_type = input.types().to_vec();
+    let n = cfg.rows;
+    let name = cfg.name.clone().unwrap_or_else(|| format!("{}_lead", input_type[cfg.column].name));
+    let output = sender.initialize(output_type(&input_type, name, ValueType::Any))?;
+
+    /* Pending rows, the number of rows sent so far and the pending rows of every partition. */
+    let mut pending: VecDeque<(Row, Option<Value>)> = VecDeque::new();
+    let mut sent = 0usize;
+    let mut waiting: HashMap<Vec<Value>, VecDeque<usize>> = HashMap::new();
+
+    while let Ok(row) = input.read() {
+        let partition = waiting.entry(partition_key(&row, cfg.partition)).or_default();
+        if partition.len() == n {
+            if let Some(idx) = partition.pop_front() {
+                pending[idx - sent].1 = Some(row.cells()[cfg.column].clone());
+            }
+        }
+        partition.push_back(sent + pending.len());
+        pending.push_back((row, None));
+
+        while let Some((_, Some(_))) = pending.front() {
+            if let Some((mut row, Some(value))) = pending.pop_front() {
+                row.push(value);
+                output.send(row)?;
+                sent += 1;
+            }
+        }
+    }
+    for (mut row, value) in pending {
+        row.push(value.unwrap_or(Value::Empty()));
+        output.send(row)?;
+    }
+    Ok(())
+}
+
+pub fn window(context: ExecutionContext) -> CrushResult<()> {
+    match context.input.recv()?.readable() {
+        Some(mut input) => {
+            let cfg = parse(input.types(), context.arguments)?;
+            run_window(cfg, input.as_mut(), context.output)
+        }
+        None => error("Expected a stream"),
+    }
+}
+
+pub fn lag(context: ExecutionContext) -> CrushResult<()> {
+    match context.input.recv()?.readable() {
+        Some(mut input) => {
+            let cfg = parse_offset(input.types(), context.arguments)?;
+            run_lag(cfg, input.as_mut(), context.output)
+        }
+        None => error("Expected a stream"),
+    }
+}
+
+pub fn lead(context: ExecutionContext) -> CrushResult<()> {
+    match context.input.recv()?.readable() {
+        Some(mut input) => {
+            let cfg = parse_offset(input.types(), context.arguments)?;
+            run_lead(cfg, input.as_mut(), context.output)
+        }
+        None => error("Expected a stream"),
+    }
+}
@@ -1,6 +1,6 @@
 use crate::lang::scope::Scope;
 use crate::lang::errors::{CrushResult, error, to_crush_error, argument_error};
-use crate::lang::{value::Value};
+use crate::lang::{value::Value, value::ValueType};
 use crate::util::file::{home, cwd};
 use std::path::PathBuf;
 use crate::lang::execution_context::ExecutionContext;
@@ -39,6 +39,18 @@ fn halp(o: &dyn Help, printer: &Printer) {
         }.as_str());
 }
 
+/**
+  Like halp, but also lists the methods that have been added to the type in the current scope.
+*/
+fn type_halp(t: &ValueType, env: &Scope, printer: &Printer) -> CrushResult<()> {
+    printer.line(
+        match t.long_help_in(env)? {
+            None => format!("{}\n\n    {}", t.signature(), t.short_help()),
+            Some(long_help) => format!("{}\n\n    {}\n\n{}", t.signature(), t.short_help(), long_help),
+        }.as_str());
+    Ok(())
+}
+
 pub fn help(mut context: ExecutionContext) -> CrushResult<()> {
     match context.arguments.len() {
         0 => {
@@ -63,8 +75,9 @@ members of a value, write "dir <value>".
             match v {
                 Value::Command(cmd) =>
                     halp(cmd.help(), &context.printer),
-                Value::Type(t) => halp(&t, &context.printer),
-                v => halp(&v, &context.printer),
+                Value::Type(t) => type_halp(&t, &context.env, &context.printer)?,
+                Value::Scope(s) => halp(&s, &context.printer),
+                v => type_halp(&v.value_type(), &context.env, &context.printer)?,
             }
             Ok(())
         }
@@ -0,0 +1,230 @@
+use crate::lang::errors::{CrushResult, argument_error};
+use crate::lang::{value::Value, execution_context::ExecutionContext};
+use crate::lang::execution_context::{ArgumentVector, This};
+use std::collections::HashMap;
+use lazy_static::lazy_static;
+use crate::lang::command::CrushCommand;
+use crate::lang::command::TypeMap;
+use bigdecimal::BigDecimal;
+use num_bigint::{BigInt, Sign};
+use num_traits::{Zero, Signed};
+use std::cmp::Ordering;
+
+fn full(name: &'static str) -> Vec<&'static str> {
+    vec!["global", "types", "decimal", name]
+}
+
+/**
+  The number of digits after the decimal point kept when dividing using the `//` operator.
+*/
+const DIVISION_SCALE: i64 = 20;
+
+lazy_static! {
+    pub static ref METHODS: HashMap<String, Box<dyn CrushCommand +  Sync + Send>> = {
+        let mut res: HashMap<String, Box<dyn CrushCommand +  Send + Sync>> = HashMap::new();
+        res.declare(
+            full("__add__"), add, false,
+            "decimal + term:(integer|decimal)",
+            "Add this number and the specified term",
+            None);
+        res.declare(
+            full("__sub__"), sub, false,
+            "decimal - term:(integer|decimal)",
+            "Subtract the specified term from this number",
+            None);
+        res.declare(
+            full("__mul__"), mul, false,
+            "decimal * factor:(integer|decimal)",
+            "Multiply this number by the specified factor",
+            None);
+        res.declare(
+            full("__div__"), div, false,
+            "decimal / factor:(integer|decimal)",
+            "Divide this number by the specified factor",
+            Some(r#"    The result is rounded to 20 digits after the decimal point using banker's
+    rounding. Use decimal:div to control the scale and rounding of the result."#));
+        res.declare(
+            full("__neg__"), neg, false,
+            "neg decimal", "Negate this decimal", None);
+        res.declare(
+            full("div"), div_scale, false,
+            "decimal:div factor:(integer|decimal) scale:integer [rounding:string]",
+            "Divide this number by the specified factor, keeping scale digits after the decimal point",
+            Some(r#"    The rounding mode is one of half_even (the default), half_up, half_down, up,
+    down, ceiling and floor.
+
+    Example:
+
+    1.0d:div 3 4"#));
+        res.declare(
+            full("round"), round, false,
+            "decimal:round [scale:integer] [rounding:string]",
+            "Round this number to the specified number of digits after the decimal point",
+            Some(r#"    The scale defaults to zero. The rounding mode is one of half_even (the default),
+    half_up, half_down, up, down, ceiling and floor.
+
+    Example:
+
+    2.345d:round 2 "half_up""#));
+        res.declare(
+            full("scale"), scale, false,
+            "decimal:scale",
+            "The number of digits after the decimal point in this number",
+            None);
+        res
+    };
+}
+
+#[derive(Clone, Copy)]
+pub enum Rounding {
+    HalfEven,
+    HalfUp,
+    HalfDown,
+    Up,
+    Down,
+    Ceiling,
+    Floor,
+}
+
+impl Rounding {
+    pub fn parse(s: &str) -> CrushResult<Rounding> {
+        match s {
+            "half_even" => Ok(Rounding::HalfEven),
+            "half_up" => Ok(Rounding::HalfUp),
+            "half_down" => Ok(Rounding::HalfDown),
+            "up" => Ok(Rounding::Up),
+            "down" => Ok(Rounding::Down),
+            "ceiling" => Ok(Rounding::Ceiling),
+            "floor" => Ok(Rounding::Floor),
+            _ => argument_error(format!("Unknown rounding mode {}", s).as_str()),
+        }
+    }
+}
+
+/**
+  Round the number to the specified number of digits after the decimal point.
+*/
+pub fn round_decimal(value: &BigDecimal, scale: i64, rounding: Rounding) -> BigDecimal {
+    let (digits, current) = value.as_bigint_and_exponent();
+    if current <= scale {
+        return value.with_scale(scale);
+    }
+    let divisor = num_traits::pow(BigInt::from(10), (current - scale) as usize);
+    let quotient = &digits / &divisor;
+    let remainder = &digits % &divisor;
+    if remainder.is_zero() {
+        return BigDecimal::new(quotient, scale);
+    }
+    let negative = digits.sign() == Sign::Minus;
+    let half = (remainder.abs() * BigInt::from(2)).cmp(&divisor);
+    let away_from_zero = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => true,
+        Rounding::Ceiling => !negative,
+        Rounding::Floor => negative,
+        Rounding::HalfUp => half != Ordering::Less,
+        Rounding::HalfDown => half == Ordering::Greater,
+        Rounding::HalfEven =>
+            half == Ordering::Greater ||
+                (half == Ordering::Equal && !(&quotient % BigInt::from(2)).is_zero()),
+    };
+    let quotient = match (away_from_zero, negative) {
+        (false, _) => quotient,
+        (true, false) => quotient + BigInt::from(1),
+        (true, true) => quotient - BigInt::from(1),
+    };
+    BigDecimal::new(quotient, scale)
+}
+
+/**
+  Remove trailing zeros after the decimal point.
+*/
+fn normalize(value: BigDecimal) -> BigDecimal {
+    let (mut digits, mut scale) = value.into_bigint_and_exponent();
+    let ten = BigInt::from(10);
+    while scale > 0 && !digits.is_zero() && (&digits % &ten).is_zero() {
+        digits /= &ten;
+        scale -= 1;
+    }
+    BigDecimal::new(digits, scale)
+}
+
+pub fn divide(a: BigDecimal, b: BigDecimal, scale: i64, rounding: Rounding) -> CrushResult<BigDecimal> {
+    if b.is_zero() {
+        return argument_error("Division by zero");
+    }
+    Ok(round_decimal(&(a / b), scale, rounding))
+}
+
+/**
+  Divide two numbers using the default scale and rounding of the `//` operator.
+*/
+pub fn default_divide(a: BigDecimal, b: BigDecimal) -> CrushResult<BigDecimal> {
+    Ok(normalize(divide(a, b, DIVISION_SCALE, Rounding::HalfEven)?))
+}
+
+fn to_decimal(value: Value) -> CrushResult<BigDecimal> {
+    match value {
+        Value::Decimal(d) => Ok(d),
+        Value::Integer(i) => Ok(BigDecimal::new(BigInt::from(i), 0)),
+        Value::BigInteger(i) => Ok(BigDecimal::new(i, 0)),
+        v => argument_error(format!("Expected an integer or a decimal, got a {}", v.value_type().to_string()).as_str()),
+    }
+}
+
+fn decimal_op(mut context: ExecutionContext, op: fn(BigDecimal, BigDecimal) -> CrushResult<BigDecimal>) -> CrushResult<()> {
+    context.arguments.check_len(1)?;
+    let this = context.this.decimal()?;
+    let other = to_decimal(context.arguments.value(0)?)?;
+    context.output.send(Value::Decimal(op(this, other)?))
+}
+
+fn add(context: ExecutionContext) -> CrushResult<()> {
+    decimal_op(context, |a, b| Ok(a + b))
+}
+
+fn sub(context: ExecutionContext) -> CrushResult<()> {
+    decimal_op(context, |a, b| Ok(a - b))
+}
+
+fn mul(context: ExecutionContext) -> CrushResult<()> {
+    decimal_op(context, |a, b| Ok(a * b))
+}
+
+fn div(context: ExecutionContext) -> CrushResult<()> {
+    decimal_op(context, default_divide)
+}
+
+fn neg(context: ExecutionContext) -> CrushResult<()> {
+    context.arguments.check_len(0)?;
+    context.output.send(Value::Decimal(-context.this.decimal()?))
+}
+
+fn div_scale(mut context: ExecutionContext) -> CrushResult<()> {
+    context.arguments.check_len_range(2, 3)?;
+    let this = context.this.decimal()?;
+    let other = to_decimal(context.arguments.value(0)?)?;
+    let scale = context.arguments.integer(1)? as i64;
+    let rounding = match context.arguments.optional_string(2)? {
+        Some(r) => Rounding::parse(&r)?,
+        None => Rounding::HalfEven,
+    };
+    context.output.send(Value::Decimal(divide(this, other, scale, rounding)?))
+}
+
+fn round(mut context: ExecutionContext) -> CrushResult<()> {
+    context.arguments.check_len_range(0, 2)?;
+    let this = context.this.decimal()?;
+    let scale = if context.arguments.is_empty() { 0 } else { context.arguments.integer(0)? as i64 };
+    let rounding = if context.arguments.len() > 1 {
+        Rounding::parse(&context.arguments.string(1)?)?
+    } else {
+        Rounding::HalfEven
+    };
+    context.output.send(Value::Decimal(round_decimal(&this, scale, rounding)))
+}
+
+fn scale(context: ExecutionContext) -> CrushResult<()> {
+    context.arguments.check_len(0)?;
+    context.output.send(Value::Integer(context.this.decimal()?.as_bigint_and_exponent().1 as i128))
+}
@@ -1,14 +1,19 @@
 use crate::lang::execution_context::{ExecutionContext, This, ArgumentVector};
-use crate::lang::errors::{CrushResult, to_crush_error};
+use crate::lang::errors::{CrushResult, to_crush_error, mandate};
 use crate::lang::r#struct::Struct;
 use crate::lang::value::Value;
 use std::fs::metadata;
-use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::{MetadataExt, fchown};
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 use crate::lang::command::CrushCommand;
 use crate::lang::serialization::{serialize, deserialize};
 use crate::lang::command::TypeMap;
+use crate::util::file::feed;
+use std::fs::{File, OpenOptions, rename, remove_file, symlink_metadata};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
 
 fn full(name: &'static str) -> Vec<&'static str> {
     vec!["global", "types", "file", name]
@@ -51,6 +56,28 @@ lazy_static! {
     Example:
 
     ls | ./some_file:to"#));
+        res.declare(full("write"),
+            write, true,
+            "file:write [value:value]",
+            "Write a value to the specified file as text or binary data",
+            Some(r#"    The value can either be specified as an argument or it can be read from a pipe.
+    Binary data and strings are written as is, tables are written one line per row
+    with tab separated cells. The file is replaced atomically, readers will never
+    see a partially written file.
+
+    Example:
+
+    http "https://example.com/" | body | ./page.html:write"#));
+        res.declare(full("append"),
+            append, true,
+            "file:append [value:value]",
+            "Append a value to the specified file as text or binary data",
+            Some(r#"    The value is rendered in the same way as by file:write. If the file does not
+    exist, it is created.
+
+    Example:
+
+    ps | where {cpu > 50.0} | ./busy.log:append"#));
         res.declare(full("from"),
             from, true,
             "file:from",
@@ -104,3 +131,71 @@ pub fn from(context: ExecutionContext) -> CrushResult<()> {
     context.arguments.check_len(0)?;
     context.output.send(deserialize(&file, &context.env )?)
 }
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/**
+  A file in the same directory as the target, so that it can be renamed into place. The
+  counter keeps concurrent writers in the same process apart.
+*/
+fn temp_file(file: &Path) -> CrushResult<PathBuf> {
+    let name = mandate(file.file_name(), "Invalid file name")?;
+    Ok(file.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed))))
+}
+
+/**
+  Replace the content of the file with the value. Regular files are written to a temporary file
+  with the same permissions and ownership, which is then renamed over the original, so that
+  readers never see a partially written file. Symlinks and files with several hard links are
+  written in place, since renaming would replace the link instead of the file it points to.
+*/
+pub fn replace(file: &Path, value: Value) -> CrushResult<()> {
+    let meta = symlink_metadata(file).ok();
+    match &meta {
+        Some(m) if !m.file_type().is_file() || m.nlink() > 1 => {
+            let mut out = to_crush_error(File::create(file))?;
+            to_crush_error(feed(value, &mut out))?;
+            return to_crush_error(out.flush());
+        }
+        _ => {}
+    }
+    let temp = temp_file(file)?;
+    let res = (|| {
+        let mut out = to_crush_error(File::create(&temp))?;
+        if let Some(m) = &meta {
+            to_crush_error(out.set_permissions(m.permissions()))?;
+            /* Only root may give a file away, so failing to keep the owner is not an error. */
+            let _ = fchown(&out, Some(m.uid()), Some(m.gid()));
+        }
+        to_crush_error(feed(value, &mut out))?;
+        to_crush_error(out.flush())?;
+        to_crush_error(rename(&temp, file))
+    })();
+    if res.is_err() {
+        let _ = remove_file(&temp);
+    }
+    res
+}
+
+fn input(context: &mut ExecutionContext) -> CrushResult<Value> {
+    context.arguments.check_len_range(0, 1)?;
+    if context.arguments.is_empty() { context.input.recv() } else { context.arguments.value(0) }
+}
+
+pub fn write(mut context: ExecutionContext) -> CrushResult<()> {
+    let value = input(&mut context)?;
+    let file = context.this.file()?;
+    replace(&file, value)
+}
+
+pub fn append(mut context: ExecutionContext) -> CrushResult<()> {
+    let value = input(&mut context)?;
+    let file = context.this.file()?;
+    let mut out = to_crush_error(OpenOptions::new().append(true).create(true).open(&file))?;
+    to_crush_error(feed(value, &mut out))?;
+    to_crush_error(out.flush())
+}
@@ -5,6 +5,8 @@ use std::collections::HashMap;
 use lazy_static::lazy_static;
 use crate::lang::command::CrushCommand;
 use crate::lang::command::TypeMap;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 
 fn full(name: &'static str) -> Vec<&'static str> {
     vec!["global", "types", "float", name]
@@ -50,10 +52,10 @@ lazy_static! {
     };
 }
 
-binary_op!(add, float, Integer, Float, |a, b| a+(b as f64), Float, Float, |a, b| a+b);
-binary_op!(sub, float, Integer, Float, |a, b| a-(b as f64), Float, Float, |a, b| a-b);
-binary_op!(mul, float, Integer, Float, |a, b| a*(b as f64), Float, Float, |a, b| a*b);
-binary_op!(div, float, Integer, Float, |a, b| a/(b as f64), Float, Float, |a, b| a/b);
+binary_op!(add, float, Integer, Float, |a, b| a+(b as f64), BigInteger, Float, |a, b: BigInt| a+b.to_f64().unwrap_or(f64::NAN), Float, Float, |a, b| a+b);
+binary_op!(sub, float, Integer, Float, |a, b| a-(b as f64), BigInteger, Float, |a, b: BigInt| a-b.to_f64().unwrap_or(f64::NAN), Float, Float, |a, b| a-b);
+binary_op!(mul, float, Integer, Float, |a, b| a*(b as f64), BigInteger, Float, |a, b: BigInt| a*b.to_f64().unwrap_or(f64::NAN), Float, Float, |a, b| a*b);
+binary_op!(div, float, Integer, Float, |a, b| a/(b as f64), BigInteger, Float, |a, b: BigInt| a/b.to_f64().unwrap_or(f64::NAN), Float, Float, |a, b| a/b);
 
 fn neg(context: ExecutionContext) -> CrushResult<()> {
     context.arguments.check_len(0)?;
@@ -1,10 +1,14 @@
-use crate::lang::errors::{CrushResult, argument_error};
+use crate::lang::errors::{CrushResult, argument_error, mandate};
 use crate::lang::{value::Value, execution_context::ExecutionContext};
 use crate::lang::execution_context::{ArgumentVector, This};
 use std::collections::HashMap;
 use lazy_static::lazy_static;
 use crate::lang::command::CrushCommand;
 use crate::lang::command::TypeMap;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use bigdecimal::BigDecimal;
+use crate::lib::types::decimal;
 
 fn full(name: &'static str) -> Vec<&'static str> {
     vec!["global", "types", "integer", name]
@@ -15,21 +19,21 @@ lazy_static! {
         let mut res: HashMap<String, Box<dyn CrushCommand +  Send + Sync>> = HashMap::new();
         res.declare(full("__add__"),
             add, false,
-            "integer + term:(integer|float)",
+            "integer + term:(integer|float|decimal)",
             "Add this number by the specified term",
             None);
         res.declare(full("__sub__"),
             sub, false,
-            "integer - term:(integer|float)",
+            "integer - term:(integer|float|decimal)",
             "Subtract the specified term from this number",
             None);
         res.declare(full("__mul__"),
             mul, false,
-            "integer * factor:(integer|float)", "Multiply this number with the specified factor",
+            "integer * factor:(integer|float|decimal)", "Multiply this number with the specified factor",
             None);
         res.declare(
             full("__div__"), div, false,
-            "integer / factor:(integer|float)", "Divide this number by the specified factor",
+            "integer / factor:(integer|float|decimal)", "Divide this number by the specified factor",
             None);
         res.declare(
             full("mod"), r#mod, false,
@@ -47,14 +51,76 @@ lazy_static! {
     };
 }
 
-binary_op!(add, integer, Integer, Integer, |a, b| a+b, Float, Float, |a, b| a as f64+b);
-binary_op!(sub, integer, Integer, Integer, |a, b| a-b, Float, Float, |a, b| a as f64-b);
-binary_op!(mul, integer, Integer, Integer, |a, b| a*b, Float, Float, |a, b| a as f64*b);
-binary_op!(div, integer, Integer, Integer, |a, b| a/b, Float, Float, |a, b| a as f64/b);
-binary_op!(rem, integer, Integer, Integer, |a, b| a % b);
-binary_op!(r#mod, integer, Integer, Integer, |a, b| (a % b + b) % b);
+/**
+  Perform an operation on two integers. If the result does not fit in an i128, or if either
+  argument is already a big integer, the operation is performed using arbitrary precision.
+*/
+fn integer_op(
+    mut context: ExecutionContext,
+    small: fn(i128, i128) -> Option<i128>,
+    big: fn(BigInt, BigInt) -> BigInt,
+    float: Option<fn(f64, f64) -> f64>,
+    decimal: Option<fn(BigDecimal, BigDecimal) -> CrushResult<BigDecimal>>,
+    divides: bool,
+) -> CrushResult<()> {
+    context.arguments.check_len(1)?;
+    let this = context.this.big_integer()?;
+    let res = match context.arguments.value(0)? {
+        Value::Float(b) => match float {
+            Some(op) => Value::Float(op(mandate(this.to_f64(), "Integer out of range")?, b)),
+            None => return argument_error("Expected an integer argument"),
+        },
+        Value::Integer(b) => {
+            if divides && b == 0 {
+                return argument_error("Division by zero");
+            }
+            match this.to_i128().and_then(|a| small(a, b)) {
+                Some(r) => Value::Integer(r),
+                None => Value::big_integer(big(this, BigInt::from(b))),
+            }
+        }
+        Value::BigInteger(b) => Value::big_integer(big(this, b)),
+        Value::Decimal(b) => match decimal {
+            Some(op) => Value::Decimal(op(BigDecimal::new(this, 0), b)?),
+            None => return argument_error("Expected an integer argument"),
+        },
+        _ => return argument_error("Expected only arguments of the same type"),
+    };
+    context.output.send(res)
+}
+
+fn add(context: ExecutionContext) -> CrushResult<()> {
+    integer_op(context, i128::checked_add, |a, b| a + b, Some(|a, b| a + b), Some(|a, b| Ok(a + b)), false)
+}
+
+fn sub(context: ExecutionContext) -> CrushResult<()> {
+    integer_op(context, i128::checked_sub, |a, b| a - b, Some(|a, b| a - b), Some(|a, b| Ok(a - b)), false)
+}
+
+fn mul(context: ExecutionContext) -> CrushResult<()> {
+    integer_op(context, i128::checked_mul, |a, b| a * b, Some(|a, b| a * b), Some(|a, b| Ok(a * b)), false)
+}
+
+fn div(context: ExecutionContext) -> CrushResult<()> {
+    integer_op(context, i128::checked_div, |a, b| a / b, Some(|a, b| a / b), Some(decimal::default_divide), true)
+}
+
+fn rem(context: ExecutionContext) -> CrushResult<()> {
+    integer_op(context, i128::checked_rem, |a, b| a % b, None, None, true)
+}
+
+fn r#mod(context: ExecutionContext) -> CrushResult<()> {
+    integer_op(
+        context,
+        |a, b| a.checked_rem(b)?.checked_add(b)?.checked_rem(b),
+        |a, b| (a % &b + &b) % b,
+        None,
+        None,
+        true)
+}
 
 fn neg(context: ExecutionContext) -> CrushResult<()> {
     context.arguments.check_len(0)?;
-    context.output.send(Value::Integer(-context.this.integer()?))
+    let this = context.this.big_integer()?;
+    context.output.send(Value::big_integer(-this))
 }
@@ -19,6 +19,7 @@ pub mod string;
 pub mod file;
 pub mod integer;
 pub mod float;
+pub mod decimal;
 pub mod duration;
 pub mod time;
 pub mod binary;
@@ -89,6 +90,14 @@ pub fn r#typeof(mut context: ExecutionContext) -> CrushResult<()> {
     context.output.send(Value::Type(context.arguments.value(0)?.value_type()))
 }
 
+fn extend(mut context: ExecutionContext) -> CrushResult<()> {
+    context.arguments.check_len(3)?;
+    let value_type = context.arguments.r#type(0)?;
+    let name = context.arguments.string(1)?;
+    let method = context.arguments.command(2)?;
+    context.env.extend(&value_type, &name, method)
+}
+
 fn class_set(mut context: ExecutionContext) -> CrushResult<()> {
     let this = context.this.r#struct()?;
     let value = context.arguments.value(1)?;
@@ -184,6 +193,22 @@ pub fn declare(root: &Scope) -> CrushResult<()> {
 
     p := (Point:new x=1.0 y=2.0)
     p:len"#))?;
+            env.declare_command(
+                "extend", extend, false,
+                "extend type:type name:string method:command",
+                "Add a method to all values of a builtin type",
+                Some(r#"    The method is only visible in the current scope and in scopes that can see its
+    variables, so a library can ship extensions without affecting other code.
+    Builtin methods always take precedence over methods added this way.
+
+    Example:
+
+    extend string "shout" {
+        ||
+        "{}!":format (this:upper)
+    }
+
+    "hello":shout"#))?;
             env.declare_command(
                 "materialize", materialize, true,
                 "materialize",
@@ -209,6 +234,7 @@ pub fn declare(root: &Scope) -> CrushResult<()> {
             env.declare("field", Value::Type(ValueType::Field))?;
             env.declare("empty", Value::Type(ValueType::Empty))?;
             env.declare("float", Value::Type(ValueType::Float))?;
+            env.declare("decimal", Value::Type(ValueType::Decimal))?;
             env.declare("integer", Value::Type(ValueType::Integer))?;
             env.declare("list", Value::Type(ValueType::List(Box::from(ValueType::Empty))))?;
             env.declare("string", Value::Type(ValueType::String))?;
@@ -17,6 +17,8 @@ use crate::util::file::home;
 use std::path::{PathBuf, Path};
 use crate::lang::scope::Scope;
 use crate::lang::printer::Printer;
+use nix::unistd::isatty;
+use std::os::unix::io::AsRawFd;
 
 fn crush_history_file() -> String {
         home()
@@ -31,7 +33,11 @@ fn run_interactive(global_env: Scope, printer: Printer) -> CrushResult<()> {
     printer.line("Welcome to Crush");
     printer.line(r#"Type "help" for... help."#);
 
-    let pretty_printer = create_pretty_printer(printer.clone());
+    let mut pretty_printer = create_pretty_printer(printer.clone());
+    if isatty(std::io::stdin().as_raw_fd()).unwrap_or(false) &&
+        isatty(std::io::stdout().as_raw_fd()).unwrap_or(false) {
+        pretty_printer = pretty_printer.terminal();
+    }
 
     let mut rl = Editor::<()>::new();
     let _ = rl.load_history(&crush_history_file());
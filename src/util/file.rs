@@ -1,5 +1,9 @@
 use crate::lang::errors::{CrushResult, error, to_crush_error};
-use std::path::PathBuf;
+use crate::lang::value::Value;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use users::get_user_by_name;
+use users::os::unix::UserExt;
 
 pub fn cwd() -> CrushResult<PathBuf> {
     match std::env::current_dir() {
@@ -14,3 +18,66 @@ pub fn home() -> CrushResult<PathBuf> {
         None => error("Could not find users home directory"),
     }
 }
+
+/**
+  Expand a leading ~ or ~user in a path to the home directory of the current or the named user.
+*/
+pub fn expand_tilde(path: &Path) -> CrushResult<PathBuf> {
+    let s = match path.to_str() {
+        Some(s) if s.starts_with('~') => s,
+        _ => return Ok(path.to_path_buf()),
+    };
+    let (user, rest) = match s.find('/') {
+        Some(idx) => (&s[1..idx], &s[idx + 1..]),
+        None => (&s[1..], ""),
+    };
+    let dir = if user.is_empty() {
+        home()?
+    } else {
+        match get_user_by_name(user) {
+            Some(u) => u.home_dir().to_path_buf(),
+            None => return error(format!("Unknown user {}", user).as_str()),
+        }
+    };
+    Ok(if rest.is_empty() { dir } else { dir.join(rest) })
+}
+
+/**
+  Write a value to the standard input of a child process or to a file. Binary data is passed
+  on as is, other values are rendered as text, one line per row with tab separated cells.
+*/
+pub fn feed(input: Value, out: &mut impl Write) -> std::io::Result<()> {
+    match input {
+        Value::BinaryStream(mut b) => {
+            std::io::copy(&mut b, out)?;
+        }
+        Value::Binary(b) => out.write_all(&b)?,
+        Value::String(s) => out.write_all(s.as_bytes())?,
+        value => match value.readable() {
+            Some(mut r) => {
+                while let Ok(row) = r.read() {
+                    let line = row.cells().iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\t");
+                    writeln!(out, "{}", line)?;
+                }
+            }
+            None => writeln!(out, "{}", value.to_string())?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tilde_expansion() {
+        assert_eq!(expand_tilde(Path::new("~/a/b")).unwrap(), home().unwrap().join("a/b"));
+        assert_eq!(expand_tilde(Path::new("~")).unwrap(), home().unwrap());
+        assert_eq!(expand_tilde(Path::new("a/~")).unwrap(), PathBuf::from("a/~"));
+        assert!(expand_tilde(Path::new("~no_such_user_here")).is_err());
+    }
+}